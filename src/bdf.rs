@@ -0,0 +1,308 @@
+//! Loading glyphs from BDF (Glyph Bitmap Distribution Format) font files.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::font::{center_rows, Font, FontSet};
+use crate::BadgerError;
+
+/// Rows each glyph keeps, matching the built-in 5x7 font.
+const GLYPH_ROWS: usize = 7;
+
+/// Tallest glyph cell a font may have. Cells are cropped to 7 rows anyway, and
+/// this keeps a corrupt ascent or descent from allocating a huge cell.
+const MAX_CELL_HEIGHT: usize = 256;
+
+/// A font parsed from a BDF file, cut down to the same 7 rows per glyph as the
+/// built-in font. Codepoints the file doesn't define are drawn with the built-in
+/// font instead.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BdfFont {
+    glyphs: HashMap<char, [u8; GLYPH_ROWS]>,
+}
+
+impl BdfFont {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, BadgerError> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    /// Parses the text of a BDF file.
+    ///
+    /// Every glyph is drawn into a cell as tall as the font (its ascent plus
+    /// descent), so all glyphs share a baseline, and cells taller than 7 rows
+    /// lose rows evenly from the top and bottom. Only the leftmost 8 columns
+    /// are kept.
+    pub fn parse(source: &str) -> Result<Self, BadgerError> {
+        let mut lines = source
+            .lines()
+            .enumerate()
+            .map(|(i, line)| (i + 1, line.trim()));
+        let mut bounding_box = None;
+        let mut ascent = None;
+        let mut descent = None;
+        let mut glyphs = HashMap::new();
+
+        while let Some((line_number, line)) = lines.next() {
+            let mut words = line.split_whitespace();
+            match words.next() {
+                Some("FONTBOUNDINGBOX") => {
+                    bounding_box = Some(parse_numbers::<4>(words, line_number)?)
+                }
+                Some("FONT_ASCENT") => ascent = Some(parse_numbers::<1>(words, line_number)?[0]),
+                Some("FONT_DESCENT") => descent = Some(parse_numbers::<1>(words, line_number)?[0]),
+                Some("STARTCHAR") => {
+                    let [_, height, _, y_offset] = bounding_box
+                        .ok_or_else(|| invalid(line_number, "STARTCHAR before FONTBOUNDINGBOX"))?;
+                    let ascent = match ascent {
+                        Some(ascent) => ascent,
+                        None => height
+                            .checked_add(y_offset)
+                            .ok_or_else(|| out_of_range(line_number))?,
+                    };
+                    let descent = match descent {
+                        Some(descent) => descent,
+                        None => y_offset
+                            .checked_neg()
+                            .ok_or_else(|| out_of_range(line_number))?,
+                    };
+                    if let Some((c, rows)) = parse_glyph(&mut lines, line_number, ascent, descent)?
+                    {
+                        glyphs.insert(c, rows);
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Ok(Self { glyphs })
+    }
+
+    /// Whether the file defined a glyph for `c`.
+    pub fn contains(&self, c: char) -> bool {
+        self.glyphs.contains_key(&c)
+    }
+}
+
+impl Font for BdfFont {
+    fn glyph(&self, c: char) -> [u8; 11] {
+        match self.glyphs.get(&c) {
+            Some(rows) => center_rows(rows),
+            None => FontSet::Small.glyph(c),
+        }
+    }
+
+    fn has_glyph(&self, c: char) -> bool {
+        self.contains(c) || FontSet::Small.has_glyph(c)
+    }
+}
+
+/// Parses one glyph, from the line after `STARTCHAR` through `ENDCHAR`.
+/// Returns `None` for glyphs without a Unicode encoding.
+fn parse_glyph<'a>(
+    lines: &mut impl Iterator<Item = (usize, &'a str)>,
+    start_line: usize,
+    ascent: i32,
+    descent: i32,
+) -> Result<Option<(char, [u8; GLYPH_ROWS])>, BadgerError> {
+    let mut encoding = None;
+    let mut glyph_box = None;
+
+    while let Some((line_number, line)) = lines.next() {
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("ENCODING") => encoding = Some(parse_numbers::<1>(words, line_number)?[0]),
+            Some("BBX") => glyph_box = Some(parse_numbers::<4>(words, line_number)?),
+            Some("BITMAP") => {
+                let [width, height, x_offset, y_offset] =
+                    glyph_box.ok_or_else(|| invalid(line_number, "BITMAP before BBX"))?;
+
+                // Rows of the full-height cell, top first, with bit 0 as the
+                // leftmost column.
+                let cell_height = ascent
+                    .checked_add(descent)
+                    .ok_or_else(|| out_of_range(line_number))?
+                    .max(0) as usize;
+                if cell_height > MAX_CELL_HEIGHT {
+                    return Err(invalid(line_number, "glyph cell is too tall"));
+                }
+                let mut cell = vec![0u32; cell_height];
+                let top = y_offset
+                    .checked_add(height)
+                    .and_then(|bottom| ascent.checked_sub(bottom))
+                    .ok_or_else(|| out_of_range(line_number))?;
+
+                for row in 0..height {
+                    let (line_number, hex) = lines
+                        .next()
+                        .ok_or_else(|| invalid(line_number, "BITMAP ended early"))?;
+                    let bits = parse_row(hex, width)
+                        .ok_or_else(|| invalid(line_number, "malformed BITMAP row"))?;
+                    let shifted = if x_offset >= 0 {
+                        bits.checked_shl(x_offset as u32).unwrap_or(0)
+                    } else {
+                        bits.checked_shr(x_offset.unsigned_abs()).unwrap_or(0)
+                    };
+                    if let Some(cell_row) = top
+                        .checked_add(row)
+                        .and_then(|y| usize::try_from(y).ok())
+                        .and_then(|y| cell.get_mut(y))
+                    {
+                        *cell_row |= shifted;
+                    }
+                }
+
+                let mut rows = [0u8; GLYPH_ROWS];
+                let skip = cell_height.saturating_sub(GLYPH_ROWS) / 2;
+                let pad = GLYPH_ROWS.saturating_sub(cell_height) / 2;
+                for (row, bits) in rows[pad..].iter_mut().zip(&cell[skip..]) {
+                    // Bit 0 is the leftmost column here, but the MSB is in a chunk.
+                    *row = (*bits as u8).reverse_bits();
+                }
+
+                let c = encoding
+                    .and_then(|code| u32::try_from(code).ok())
+                    .and_then(char::from_u32);
+                return Ok(c.map(|c| (c, rows)));
+            }
+            Some("ENDCHAR") => return Ok(None),
+            _ => {}
+        }
+    }
+
+    Err(invalid(start_line, "STARTCHAR without ENDCHAR"))
+}
+
+/// Reads a BITMAP row of `width` pixels, padded to whole bytes with the
+/// leftmost pixel in the MSB, into a mask with bit 0 as the leftmost pixel.
+/// Columns past the 32nd are dropped.
+fn parse_row(hex: &str, width: i32) -> Option<u32> {
+    let bytes = (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            hex.get(i..i + 2)
+                .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+        })
+        .collect::<Option<Vec<u8>>>()?;
+
+    Some(
+        (0..width.clamp(0, 32) as usize)
+            .filter(|&x| {
+                bytes
+                    .get(x / 8)
+                    .is_some_and(|byte| byte & (0x80 >> (x % 8)) != 0)
+            })
+            .fold(0, |bits, x| bits | 1 << x),
+    )
+}
+
+fn parse_numbers<'a, const N: usize>(
+    mut words: impl Iterator<Item = &'a str>,
+    line_number: usize,
+) -> Result<[i32; N], BadgerError> {
+    let mut numbers = [0; N];
+    for number in &mut numbers {
+        *number = words
+            .next()
+            .and_then(|word| word.parse().ok())
+            .ok_or_else(|| invalid(line_number, "expected a number"))?;
+    }
+    Ok(numbers)
+}
+
+fn invalid(line: usize, message: &str) -> BadgerError {
+    BadgerError::InvalidFont {
+        line,
+        message: message.to_string(),
+    }
+}
+
+fn out_of_range(line: usize) -> BadgerError {
+    invalid(line, "glyph metrics out of range")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 9-row font: 'A' fills the whole cell with one pixel per row, and 'I'
+    /// is a short bar sitting on the baseline.
+    const FIXTURE: &str = "\
+STARTFONT 2.1
+FONT -test-fixture
+FONTBOUNDINGBOX 8 9 0 -2
+FONT_ASCENT 7
+FONT_DESCENT 2
+CHARS 2
+STARTCHAR A
+ENCODING 65
+BBX 8 9 0 -2
+BITMAP
+80
+40
+20
+10
+08
+04
+02
+01
+FF
+ENDCHAR
+STARTCHAR I
+ENCODING 73
+BBX 1 5 2 0
+BITMAP
+80
+80
+80
+80
+80
+ENDCHAR
+ENDFONT
+";
+
+    #[test]
+    fn parse_places_glyphs_on_a_shared_baseline() {
+        let font = BdfFont::parse(FIXTURE).unwrap();
+
+        assert!(font.contains('I'));
+        assert_eq!(
+            font.glyphs[&'I'],
+            [0x00, 0x20, 0x20, 0x20, 0x20, 0x20, 0x00]
+        );
+    }
+
+    #[test]
+    fn parse_crops_tall_glyphs_from_the_middle() {
+        let font = BdfFont::parse(FIXTURE).unwrap();
+
+        // The 9-row cell loses its top and bottom row
+        assert_eq!(
+            font.glyphs[&'A'],
+            [0x40, 0x20, 0x10, 0x08, 0x04, 0x02, 0x01]
+        );
+    }
+
+    #[test]
+    fn missing_glyphs_fall_back_to_the_built_in_font() {
+        let font = BdfFont::parse(FIXTURE).unwrap();
+
+        assert!(!font.contains('B'));
+        assert!(font.has_glyph('B'));
+        assert_eq!(font.glyph('B'), FontSet::Small.glyph('B'));
+    }
+
+    #[test]
+    fn parse_rejects_out_of_range_metrics() {
+        let overflowing = FIXTURE.replace("FONT_ASCENT 7", "FONT_ASCENT 2147483647");
+        assert!(matches!(
+            BdfFont::parse(&overflowing),
+            Err(BadgerError::InvalidFont { .. })
+        ));
+
+        let too_tall = FIXTURE.replace("FONT_ASCENT 7", "FONT_ASCENT 100000000");
+        assert!(matches!(
+            BdfFont::parse(&too_tall),
+            Err(BadgerError::InvalidFont { .. })
+        ));
+    }
+}
@@ -0,0 +1,1038 @@
+//! Talking to a badge over Bluetooth LE.
+//!
+//! [`Badge::connect`] scans for a badge and discovers its services once, and
+//! the returned handle can then be sent any number of payloads without
+//! repeating that work.
+
+use std::time::Duration;
+
+use btleplug::api::{
+    BDAddr, Central, CentralEvent, CharPropFlags, Characteristic, Manager as _, Peripheral,
+    ScanFilter, WriteType,
+};
+use btleplug::platform::{Adapter, Manager, Peripheral as PlatformPeripheral};
+use futures::future;
+use futures::stream::{self, BoxStream, StreamExt};
+use tokio::{task, time};
+use uuid::Uuid;
+
+use crate::{BadgerError, Bitmap, Data, OverwidePolicy, PANEL_WIDTH};
+
+/// The service stock LSLED badges advertise and accept payloads on.
+pub const SERVICE_UUID: Uuid = Uuid::from_u128(0x0000fee000001000800000805f9b34fb);
+
+/// The characteristic within `SERVICE_UUID` payloads are written to.
+pub const CHARACTERISTIC_UUID: Uuid = Uuid::from_u128(0x0000fee100001000800000805f9b34fb);
+
+/// The name stock badges advertise.
+pub const BADGE_NAME: &str = "LSLED";
+
+/// Shown in place of the name of a peripheral that hasn't advertised one.
+const UNKNOWN_NAME: &str = "(peripheral name unknown)";
+
+/// How long to scan for a matching badge before giving up.
+pub const SCAN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How many times service discovery is attempted before giving up.
+const DISCOVERY_ATTEMPTS: usize = 3;
+
+/// How many times an upload reconnects to a badge that dropped the link before
+/// giving up.
+const RECONNECT_ATTEMPTS: u32 = 3;
+
+/// Delay before the first retry of a failed chunk write.
+const WRITE_RETRY_DELAY: Duration = Duration::from_millis(50);
+
+/// Options for how a `Badge` is found and written to.
+#[derive(Debug, Clone)]
+pub struct SendOptions {
+    /// Resend the first chunk before the upload to wake the badge up.
+    pub warmup: bool,
+    /// Print a line per chunk written.
+    pub verbose: bool,
+    /// Make `Badge::disconnect` skip the disconnect round-trip and leave
+    /// teardown to the OS.
+    pub no_disconnect: bool,
+    /// Scan with this adapter, by index or name, instead of the first one.
+    pub adapter: Option<String>,
+    /// Connect to the badge at this address instead of looking it up by name.
+    pub address: Option<BDAddr>,
+    /// How many times each chunk write is attempted before giving up.
+    pub write_attempts: u32,
+    /// Retry failed chunk writes as writes with response.
+    pub retry_with_response: bool,
+    /// Write chunks this way instead of going by the characteristic's
+    /// properties.
+    pub write_type: Option<WriteType>,
+    /// How long to wait between chunk writes.
+    pub chunk_delay: Duration,
+    /// The service to scan for and look the characteristic up in.
+    pub service_uuid: Uuid,
+    /// The characteristic payloads are written to.
+    pub characteristic_uuid: Uuid,
+}
+
+impl Default for SendOptions {
+    fn default() -> Self {
+        Self {
+            warmup: false,
+            verbose: false,
+            no_disconnect: false,
+            adapter: None,
+            address: None,
+            write_attempts: 3,
+            retry_with_response: false,
+            write_type: None,
+            chunk_delay: Duration::from_millis(1),
+            service_uuid: SERVICE_UUID,
+            characteristic_uuid: CHARACTERISTIC_UUID,
+        }
+    }
+}
+
+/// A connected badge and the characteristic payloads are written to.
+///
+/// Cloning a `Badge` is cheap and shares the connection, so it can be handed
+/// to another task.
+#[derive(Debug, Clone)]
+pub struct Badge<P = PlatformPeripheral> {
+    peripheral: P,
+    characteristic: Characteristic,
+    options: SendOptions,
+}
+
+impl Badge {
+    /// Scans for the badge and connects to it. The badge is the first
+    /// peripheral named "LSLED", or the one at `options.address` if given.
+    pub async fn connect(options: SendOptions) -> Result<Self, BadgerError> {
+        let peripheral = find_badge(&options).await?;
+        Self::from_peripheral(peripheral, options).await
+    }
+}
+
+/// Scans for the badge `Badge::connect` connects to, without connecting.
+pub async fn find_badge(options: &SendOptions) -> Result<PlatformPeripheral, BadgerError> {
+    let found = scan(
+        options,
+        SCAN_TIMEOUT,
+        Some(1),
+        |peripheral, name| match options.address {
+            Some(address) => peripheral.address() == address,
+            None => name == Some(BADGE_NAME),
+        },
+    )
+    .await?;
+    found.into_iter().next().ok_or(BadgerError::DeviceNotFound)
+}
+
+/// Scans for up to `window` for peripherals advertising `options.service_uuid`
+/// and returns each distinct one `wanted` accepts, in the order they showed
+/// up. `wanted` gets every peripheral along with the name it advertises, if
+/// any, and the scan ends early once `limit` of them have been accepted.
+pub async fn scan(
+    options: &SendOptions,
+    window: Duration,
+    limit: Option<usize>,
+    mut wanted: impl FnMut(&PlatformPeripheral, Option<&str>) -> bool,
+) -> Result<Vec<PlatformPeripheral>, BadgerError> {
+    let manager = Manager::new().await?;
+
+    let central = get_central(&manager, options.adapter.as_deref()).await?;
+
+    let central_state = central.adapter_state().await?;
+    log::debug!("CentralState: {:?}", central_state);
+
+    let mut events = central.events().await?;
+
+    let mut scan_filter = ScanFilter::default();
+    scan_filter.services.push(options.service_uuid);
+
+    central.start_scan(scan_filter).await?;
+
+    let mut found: Vec<PlatformPeripheral> = vec![];
+    let scanning = time::timeout(window, async {
+        while let Some(event) = events.next().await {
+            // Badges the adapter already knew about only show up as updated,
+            // and a badge may only report its name in a later update, so
+            // both count
+            let device = match event {
+                CentralEvent::DeviceDiscovered(device) => {
+                    log::debug!("DeviceDiscovered: {:?}", device);
+                    device
+                }
+                CentralEvent::DeviceUpdated(device) => device,
+                _ => continue,
+            };
+            let peripheral = central.peripheral(&device).await?;
+            if found
+                .iter()
+                .any(|badge| badge.address() == peripheral.address())
+            {
+                continue;
+            }
+
+            let properties = peripheral.properties().await?;
+            let name = properties.and_then(|properties| properties.local_name);
+            if !wanted(&peripheral, name.as_deref()) {
+                continue;
+            }
+            log::info!(
+                "Found {} ({})",
+                name.as_deref().unwrap_or(UNKNOWN_NAME),
+                peripheral.address()
+            );
+            found.push(peripheral);
+
+            if limit.is_some_and(|limit| found.len() >= limit) {
+                break;
+            }
+        }
+
+        Ok::<_, BadgerError>(())
+    });
+    let result = scanning.await.unwrap_or(Ok(()));
+    central.stop_scan().await?;
+    result?;
+
+    Ok(found)
+}
+
+/// Returns the name `peripheral` advertises, or a placeholder if it hasn't
+/// sent one.
+pub async fn peripheral_name<P: Peripheral>(peripheral: &P) -> Result<String, BadgerError> {
+    Ok(peripheral
+        .properties()
+        .await?
+        .and_then(|properties| properties.local_name)
+        .unwrap_or(String::from(UNKNOWN_NAME)))
+}
+
+impl<P: Peripheral> Badge<P> {
+    /// Connects to a badge that has already been found, such as by scanning
+    /// with the adapter from `get_central`, and looks up its characteristic.
+    pub async fn from_peripheral(peripheral: P, options: SendOptions) -> Result<Self, BadgerError> {
+        if !peripheral.is_connected().await? {
+            log::info!("Connecting to {}...", peripheral.address());
+            peripheral.connect().await?;
+        }
+        let characteristic = find_characteristic(
+            &peripheral,
+            options.service_uuid,
+            options.characteristic_uuid,
+        )
+        .await?;
+
+        Ok(Self {
+            peripheral,
+            characteristic,
+            options,
+        })
+    }
+
+    pub fn peripheral(&self) -> &P {
+        &self.peripheral
+    }
+
+    pub fn characteristic(&self) -> &Characteristic {
+        &self.characteristic
+    }
+
+    pub fn options(&self) -> &SendOptions {
+        &self.options
+    }
+
+    /// Changes how later uploads are written, for example to turn off
+    /// `warmup` once the badge is awake.
+    pub fn options_mut(&mut self) -> &mut SendOptions {
+        &mut self.options
+    }
+
+    /// Uploads `data` to the badge.
+    pub async fn send(&self, data: &Data) -> Result<(), BadgerError> {
+        self.send_payload(&data.to_bytes()?, |_, _| {}).await
+    }
+
+    /// Uploads an already encoded payload, calling `progress` with the number
+    /// of 16-byte chunks written so far and the total after each one.
+    ///
+    /// If the badge disconnects partway through, it's reconnected and the
+    /// upload picks up again at the chunk that failed.
+    pub async fn send_payload(
+        &self,
+        data_bytes: &[u8],
+        mut progress: impl FnMut(usize, usize) + Send,
+    ) -> Result<(), BadgerError> {
+        upload_resuming(
+            &self.peripheral,
+            &self.characteristic,
+            data_bytes,
+            &self.options,
+            &mut progress,
+        )
+        .await
+    }
+
+    /// Disconnects from the badge, unless `options.no_disconnect` is set.
+    pub async fn disconnect(&self) -> Result<(), BadgerError> {
+        if !self.options.no_disconnect {
+            self.peripheral.disconnect().await?;
+        }
+        Ok(())
+    }
+
+    /// Subscribes to the button presses the badge reports, from the first
+    /// notify characteristic in `options.service_uuid`. Badges without one,
+    /// like stock LSLED units, give a stream that has already ended.
+    pub async fn button_events(&self) -> Result<BoxStream<'static, ButtonEvent>, BadgerError> {
+        let characteristic = self
+            .peripheral
+            .services()
+            .into_iter()
+            .filter(|service| service.uuid == self.options.service_uuid)
+            .flat_map(|service| service.characteristics)
+            .find(|characteristic| characteristic.properties.contains(CharPropFlags::NOTIFY));
+        let Some(characteristic) = characteristic else {
+            return Ok(stream::empty().boxed());
+        };
+
+        self.peripheral.subscribe(&characteristic).await?;
+        let notifications = self.peripheral.notifications().await?;
+        Ok(notifications
+            .filter_map(move |notification| {
+                future::ready(
+                    (notification.uuid == characteristic.uuid)
+                        .then(|| ButtonEvent::from_notification(&notification.value))
+                        .flatten(),
+                )
+            })
+            .boxed())
+    }
+}
+
+/// A button press reported by the badge.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ButtonEvent {
+    /// Which button was pressed, as numbered by the badge.
+    pub button: u8,
+}
+
+impl ButtonEvent {
+    /// Parses a notification from the badge's button characteristic, whose
+    /// first byte is the number of the button pressed. Empty notifications
+    /// aren't presses.
+    pub fn from_notification(value: &[u8]) -> Option<Self> {
+        value.first().map(|&button| Self { button })
+    }
+}
+
+/// Flashes `text` onto the first badge found, in the default mode.
+pub async fn flash(text: &str) -> Result<(), BadgerError> {
+    send(&flash_data(text)?, &SendOptions::default()).await
+}
+
+/// Like `flash`, but onto `peripheral` instead of a badge found by scanning.
+pub async fn flash_with<P: Peripheral>(peripheral: P, text: &str) -> Result<(), BadgerError> {
+    send_with(peripheral, &flash_data(text)?, SendOptions::default()).await
+}
+
+/// Scans for the badge, uploads `data` to it and disconnects.
+pub async fn send(data: &Data, options: &SendOptions) -> Result<(), BadgerError> {
+    // Build the payload first, so bad input fails before any BLE work
+    let data_bytes = data.to_bytes()?;
+    let peripheral = find_badge(options).await?;
+    send_bytes_to(peripheral, &data_bytes, options.clone()).await
+}
+
+/// Like `send`, but to `peripheral` instead of a badge found by scanning.
+pub async fn send_with<P: Peripheral>(
+    peripheral: P,
+    data: &Data,
+    options: SendOptions,
+) -> Result<(), BadgerError> {
+    send_bytes_to(peripheral, &data.to_bytes()?, options).await
+}
+
+/// Connects to `peripheral`, uploads `data_bytes` and disconnects.
+async fn send_bytes_to<P: Peripheral>(
+    peripheral: P,
+    data_bytes: &[u8],
+    options: SendOptions,
+) -> Result<(), BadgerError> {
+    let badge = Badge::from_peripheral(peripheral, options).await?;
+    send_once(&badge, data_bytes).await
+}
+
+/// The payload `flash` sends for `text`.
+fn flash_data(text: &str) -> Result<Data, BadgerError> {
+    let mut bitmap = Bitmap::new();
+    bitmap.put_string(text);
+
+    let mut data = Data::new();
+    data.push_bitmap(bitmap);
+    data.resolve_overwide_banks(PANEL_WIDTH, OverwidePolicy::default())?;
+    Ok(data)
+}
+
+/// Uploads `data_bytes` to a connected badge and disconnects, the part of
+/// `send` that comes after the scan.
+async fn send_once<P: Peripheral>(badge: &Badge<P>, data_bytes: &[u8]) -> Result<(), BadgerError> {
+    let result = badge.send_payload(data_bytes, |_, _| {}).await;
+    let _ = badge.disconnect().await;
+    result
+}
+
+/// Returns the Bluetooth adapter to scan with: the first one, or the one
+/// `selector` names, either by its position in the adapter list or by a
+/// substring of its description.
+pub async fn get_central(
+    manager: &Manager,
+    selector: Option<&str>,
+) -> Result<Adapter, BadgerError> {
+    let mut adapters = manager.adapters().await?;
+    let Some(selector) = selector else {
+        return adapters
+            .into_iter()
+            .next()
+            .ok_or(BadgerError::AdapterNotFound);
+    };
+
+    let mut infos = vec![];
+    for adapter in &adapters {
+        infos.push(adapter.adapter_info().await?);
+    }
+    let index = match selector.parse::<usize>() {
+        Ok(index) if index < adapters.len() => Some(index),
+        _ => infos.iter().position(|info| info.contains(selector)),
+    };
+
+    match index {
+        Some(index) => Ok(adapters.swap_remove(index)),
+        None => Err(BadgerError::AdapterNotAvailable {
+            requested: selector.to_string(),
+            available: infos
+                .iter()
+                .enumerate()
+                .map(|(i, info)| format!("{}: {}", i, info))
+                .collect(),
+        }),
+    }
+}
+
+/// Discovers the badge's services and returns the characteristic payloads are
+/// written to. Some platforms return from discovery before every service has
+/// been enumerated, so discovery is retried a few times before giving up.
+async fn find_characteristic<P: Peripheral>(
+    peripheral: &P,
+    service_uuid: Uuid,
+    characteristic_uuid: Uuid,
+) -> Result<Characteristic, BadgerError> {
+    for attempt in 0..DISCOVERY_ATTEMPTS {
+        if attempt > 0 {
+            log::info!("Characteristic not found, retrying discovery...");
+            time::sleep(Duration::from_millis(500)).await;
+        }
+        peripheral.discover_services().await?;
+
+        for service in peripheral.services() {
+            log::debug!("Checking Service: {:?}", service);
+
+            if service.uuid != service_uuid {
+                continue;
+            }
+
+            log::debug!(
+                "Service UUID {}, primary: {}",
+                service.uuid,
+                service.primary
+            );
+            for characteristic in service.characteristics {
+                log::debug!("  {:?}", characteristic);
+                if characteristic.uuid != characteristic_uuid {
+                    log::debug!("Skipping characteristic {:?}", characteristic);
+                    continue;
+                }
+
+                return Ok(characteristic);
+            }
+        }
+    }
+
+    Err(BadgerError::CharacteristicNotFound(characteristic_uuid))
+}
+
+/// Uploads `data_bytes` like `upload`, but if the badge disconnects partway
+/// through, reconnects and picks up again at the chunk that failed, up to
+/// `RECONNECT_ATTEMPTS` times.
+async fn upload_resuming<P: Peripheral>(
+    peripheral: &P,
+    characteristic: &Characteristic,
+    data_bytes: &[u8],
+    options: &SendOptions,
+    progress: &mut (impl FnMut(usize, usize) + Send),
+) -> Result<(), BadgerError> {
+    let mut characteristic = characteristic.clone();
+    let mut start = 0;
+    let mut reconnects = 0;
+
+    loop {
+        let Err(err) = upload(
+            peripheral,
+            &characteristic,
+            data_bytes,
+            start,
+            options,
+            progress,
+        )
+        .await
+        else {
+            return Ok(());
+        };
+        let BadgerError::WriteFailed { chunk, .. } = err else {
+            return Err(err);
+        };
+        // Failures on a live link are the badge rejecting writes, which
+        // reconnecting won't fix
+        if reconnects >= RECONNECT_ATTEMPTS || peripheral.is_connected().await? {
+            return Err(err);
+        }
+
+        reconnects += 1;
+        log::warn!(
+            "Badge disconnected at chunk {}, reconnecting ({} of {})...",
+            chunk,
+            reconnects,
+            RECONNECT_ATTEMPTS
+        );
+        peripheral.connect().await?;
+        characteristic = find_characteristic(
+            peripheral,
+            options.service_uuid,
+            options.characteristic_uuid,
+        )
+        .await?;
+        start = chunk;
+    }
+}
+
+/// Writes `data_bytes` to `characteristic` in 16-byte chunks, starting at
+/// chunk number `start`.
+///
+/// With `options.warmup` set, the first chunk is sent an extra time up front,
+/// since some badges drop the first write after connecting while they wake up.
+async fn upload<P: Peripheral>(
+    peripheral: &P,
+    characteristic: &Characteristic,
+    data_bytes: &[u8],
+    start: usize,
+    options: &SendOptions,
+    progress: &mut (impl FnMut(usize, usize) + Send),
+) -> Result<(), BadgerError> {
+    let total = data_bytes.chunks_exact(16).count();
+    if options.verbose {
+        log::info!("{} total chunks", total);
+    }
+    progress(start, total);
+
+    if options.warmup {
+        if let Some(chunk) = data_bytes.chunks_exact(16).nth(start) {
+            if options.verbose {
+                log::info!("Sending warm-up chunk");
+            }
+            let _ = peripheral
+                .write(characteristic, chunk, write_type(characteristic, options))
+                .await;
+        }
+    }
+    for (i, chunk) in data_bytes.chunks_exact(16).enumerate().skip(start) {
+        // Some firmwares drop chunks that arrive back to back
+        if i > start || options.warmup {
+            time::sleep(options.chunk_delay).await;
+        }
+        write_chunk(peripheral, characteristic, chunk, i, options).await?;
+        // Writes without response usually complete immediately, so give other
+        // tasks a turn before queueing the next chunk
+        task::yield_now().await;
+        if options.verbose {
+            log::info!("Wrote chunk {} of {}", i, total);
+        }
+        progress(i + 1, total);
+    }
+
+    Ok(())
+}
+
+/// Picks how to write to `characteristic`: `options.write_type` if set,
+/// otherwise without response if the characteristic supports it, since that's
+/// faster, and with response if it only supports that. A write without
+/// response to a characteristic that doesn't take one is silently dropped.
+fn write_type(characteristic: &Characteristic, options: &SendOptions) -> WriteType {
+    if let Some(write_type) = options.write_type {
+        return write_type;
+    }
+
+    let properties = characteristic.properties;
+    if !properties.contains(CharPropFlags::WRITE_WITHOUT_RESPONSE)
+        && properties.contains(CharPropFlags::WRITE)
+    {
+        WriteType::WithResponse
+    } else {
+        WriteType::WithoutResponse
+    }
+}
+
+/// Writes chunk number `index`, making up to `options.write_attempts`
+/// attempts with a doubling delay in between. A dropped chunk shifts
+/// everything after it on the display, so giving up fails the whole upload.
+async fn write_chunk<P: Peripheral>(
+    peripheral: &P,
+    characteristic: &Characteristic,
+    chunk: &[u8],
+    index: usize,
+    options: &SendOptions,
+) -> Result<(), BadgerError> {
+    let mut attempt = 1;
+    loop {
+        // Only writes with response are acknowledged, which makes them the
+        // safer bet once a write without response has failed
+        let write_type = if attempt > 1 && options.retry_with_response {
+            WriteType::WithResponse
+        } else {
+            write_type(characteristic, options)
+        };
+
+        match peripheral.write(characteristic, chunk, write_type).await {
+            Ok(()) => return Ok(()),
+            Err(source) if attempt >= options.write_attempts => {
+                return Err(BadgerError::WriteFailed {
+                    chunk: index,
+                    source,
+                });
+            }
+            Err(err) => {
+                if options.verbose {
+                    log::warn!("Error writing chunk {}, retrying: {}", index, err);
+                }
+                // Back off exponentially, but stop doubling after a few tries
+                time::sleep(WRITE_RETRY_DELAY * (1 << (attempt - 1).min(5))).await;
+                attempt += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+    use std::pin::Pin;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex, MutexGuard};
+
+    use async_trait::async_trait;
+    use btleplug::api::{Descriptor, PeripheralProperties, Service, ValueNotification};
+    use btleplug::platform::PeripheralId;
+    use futures::Stream;
+
+    use super::*;
+
+    /// What a `MockPeripheral` has been asked to do, shared by its clones.
+    #[derive(Debug, Default)]
+    struct MockState {
+        connected: bool,
+        discoveries: usize,
+        /// How many discoveries come back without the badge's service, like
+        /// a badge that's still waking up
+        hidden_discoveries: usize,
+        writes: Vec<Vec<u8>>,
+        disconnects: usize,
+        /// Read on every write, so tests can see what else ran in between
+        ticks: Arc<AtomicUsize>,
+        ticks_at_write: Vec<usize>,
+        /// The write type of every write attempt, failed ones included
+        write_types: Vec<WriteType>,
+        /// How many of the next writes fail with the link still up
+        failing_writes: usize,
+        /// How many times the link drops, each time on the write after this
+        /// many have succeeded
+        drops: usize,
+        drop_after: usize,
+        /// Sent on the button characteristic, which only exists when this
+        /// isn't empty
+        button_notifications: Vec<Vec<u8>>,
+        subscriptions: usize,
+    }
+
+    /// A badge that takes every write and remembers it. Its service shows up
+    /// once services have been discovered `hidden_discoveries + 1` times.
+    #[derive(Debug, Clone, Default)]
+    struct MockPeripheral(Arc<Mutex<MockState>>);
+
+    impl MockPeripheral {
+        fn state(&self) -> MutexGuard<'_, MockState> {
+            self.0.lock().unwrap()
+        }
+    }
+
+    const BUTTON_UUID: Uuid = Uuid::from_u128(0x0000fee200001000800000805f9b34fb);
+
+    fn button_characteristic() -> Characteristic {
+        Characteristic {
+            uuid: BUTTON_UUID,
+            service_uuid: SERVICE_UUID,
+            properties: CharPropFlags::NOTIFY,
+            descriptors: BTreeSet::new(),
+        }
+    }
+
+    fn badge_characteristic() -> Characteristic {
+        Characteristic {
+            uuid: CHARACTERISTIC_UUID,
+            service_uuid: SERVICE_UUID,
+            properties: CharPropFlags::WRITE_WITHOUT_RESPONSE,
+            descriptors: BTreeSet::new(),
+        }
+    }
+
+    #[async_trait]
+    impl Peripheral for MockPeripheral {
+        fn id(&self) -> PeripheralId {
+            unimplemented!("uploads don't look at the peripheral id")
+        }
+
+        fn address(&self) -> BDAddr {
+            BDAddr::default()
+        }
+
+        async fn properties(&self) -> btleplug::Result<Option<PeripheralProperties>> {
+            Ok(None)
+        }
+
+        fn services(&self) -> BTreeSet<Service> {
+            let state = self.state();
+            if state.discoveries <= state.hidden_discoveries {
+                return BTreeSet::new();
+            }
+            let mut characteristics = BTreeSet::from([badge_characteristic()]);
+            if !state.button_notifications.is_empty() {
+                characteristics.insert(button_characteristic());
+            }
+            BTreeSet::from([Service {
+                uuid: SERVICE_UUID,
+                primary: true,
+                characteristics,
+            }])
+        }
+
+        async fn is_connected(&self) -> btleplug::Result<bool> {
+            Ok(self.state().connected)
+        }
+
+        async fn connect(&self) -> btleplug::Result<()> {
+            self.state().connected = true;
+            Ok(())
+        }
+
+        async fn disconnect(&self) -> btleplug::Result<()> {
+            let mut state = self.state();
+            state.connected = false;
+            state.disconnects += 1;
+            Ok(())
+        }
+
+        async fn discover_services(&self) -> btleplug::Result<()> {
+            self.state().discoveries += 1;
+            Ok(())
+        }
+
+        async fn write(
+            &self,
+            _characteristic: &Characteristic,
+            data: &[u8],
+            write_type: WriteType,
+        ) -> btleplug::Result<()> {
+            let mut state = self.state();
+            state.write_types.push(write_type);
+            if !state.connected {
+                return Err(btleplug::Error::NotConnected);
+            }
+            if state.drops > 0 && state.writes.len() == state.drop_after {
+                state.drops -= 1;
+                state.connected = false;
+                return Err(btleplug::Error::NotConnected);
+            }
+            if state.failing_writes > 0 {
+                state.failing_writes -= 1;
+                return Err(btleplug::Error::TimedOut(Duration::ZERO));
+            }
+            state.writes.push(data.to_vec());
+            let ticks = state.ticks.load(Ordering::SeqCst);
+            state.ticks_at_write.push(ticks);
+            Ok(())
+        }
+
+        async fn read(&self, _characteristic: &Characteristic) -> btleplug::Result<Vec<u8>> {
+            Ok(vec![])
+        }
+
+        async fn subscribe(&self, _characteristic: &Characteristic) -> btleplug::Result<()> {
+            self.state().subscriptions += 1;
+            Ok(())
+        }
+
+        async fn unsubscribe(&self, _characteristic: &Characteristic) -> btleplug::Result<()> {
+            Ok(())
+        }
+
+        async fn notifications(
+            &self,
+        ) -> btleplug::Result<Pin<Box<dyn Stream<Item = ValueNotification> + Send>>> {
+            let notifications: Vec<ValueNotification> = self
+                .state()
+                .button_notifications
+                .iter()
+                .map(|value| ValueNotification {
+                    uuid: BUTTON_UUID,
+                    value: value.clone(),
+                })
+                .collect();
+            Ok(Box::pin(futures::stream::iter(notifications)))
+        }
+
+        async fn write_descriptor(
+            &self,
+            _descriptor: &Descriptor,
+            _data: &[u8],
+        ) -> btleplug::Result<()> {
+            Ok(())
+        }
+
+        async fn read_descriptor(&self, _descriptor: &Descriptor) -> btleplug::Result<Vec<u8>> {
+            Ok(vec![])
+        }
+    }
+
+    fn fast_options() -> SendOptions {
+        SendOptions {
+            chunk_delay: Duration::ZERO,
+            ..SendOptions::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn flash_writes_the_payload_in_chunks_and_disconnects() {
+        let peripheral = MockPeripheral::default();
+        flash_with(peripheral.clone(), "HI").await.unwrap();
+
+        let data_bytes = flash_data("HI").unwrap().to_bytes().unwrap();
+        let state = peripheral.state();
+        assert!(state.writes.iter().all(|write| write.len() == 16));
+        assert_eq!(state.writes.concat(), data_bytes);
+        assert_eq!(&state.writes[0][..4], b"wang");
+        assert_eq!(state.disconnects, 1);
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn upload_lets_other_tasks_run_between_chunks() {
+        let peripheral = MockPeripheral::default();
+        let ticks = peripheral.state().ticks.clone();
+        let ticker = tokio::spawn(async move {
+            loop {
+                ticks.fetch_add(1, Ordering::SeqCst);
+                task::yield_now().await;
+            }
+        });
+
+        let badge = Badge::from_peripheral(peripheral.clone(), fast_options())
+            .await
+            .unwrap();
+        let data_bytes = flash_data("HELLO").unwrap().to_bytes().unwrap();
+        badge.send_payload(&data_bytes, |_, _| {}).await.unwrap();
+        ticker.abort();
+
+        let state = peripheral.state();
+        assert!(state.ticks_at_write.len() > 1);
+        assert!(state
+            .ticks_at_write
+            .windows(2)
+            .all(|pair| pair[1] > pair[0]));
+    }
+
+    #[tokio::test]
+    async fn warmup_resends_the_first_chunk_up_front() {
+        let peripheral = MockPeripheral::default();
+        let options = SendOptions {
+            warmup: true,
+            ..fast_options()
+        };
+        let badge = Badge::from_peripheral(peripheral.clone(), options)
+            .await
+            .unwrap();
+        let data_bytes = flash_data("HI").unwrap().to_bytes().unwrap();
+        badge.send_payload(&data_bytes, |_, _| {}).await.unwrap();
+
+        let state = peripheral.state();
+        assert_eq!(state.writes.len(), data_bytes.len() / 16 + 1);
+        assert_eq!(state.writes[0], state.writes[1]);
+        assert_eq!(state.writes[1..].concat(), data_bytes);
+    }
+
+    #[tokio::test]
+    async fn no_disconnect_leaves_the_connection_open() {
+        let peripheral = MockPeripheral::default();
+        let options = SendOptions {
+            no_disconnect: true,
+            ..fast_options()
+        };
+        let badge = Badge::from_peripheral(peripheral.clone(), options)
+            .await
+            .unwrap();
+        let data_bytes = flash_data("HI").unwrap().to_bytes().unwrap();
+        send_once(&badge, &data_bytes).await.unwrap();
+
+        let state = peripheral.state();
+        assert_eq!(state.writes.concat(), data_bytes);
+        assert_eq!(state.disconnects, 0);
+    }
+
+    #[tokio::test]
+    async fn find_characteristic_retries_discovery_until_the_service_shows_up() {
+        let peripheral = MockPeripheral::default();
+        peripheral.state().hidden_discoveries = 1;
+
+        let characteristic = find_characteristic(&peripheral, SERVICE_UUID, CHARACTERISTIC_UUID)
+            .await
+            .unwrap();
+
+        assert_eq!(characteristic, badge_characteristic());
+        assert_eq!(peripheral.state().discoveries, 2);
+    }
+
+    #[tokio::test]
+    async fn upload_reconnects_and_resumes_at_the_chunk_that_failed() {
+        let peripheral = MockPeripheral::default();
+        {
+            let mut state = peripheral.state();
+            state.drops = 1;
+            state.drop_after = 2;
+        }
+        let badge = Badge::from_peripheral(peripheral.clone(), fast_options())
+            .await
+            .unwrap();
+        let data_bytes = flash_data("HELLO").unwrap().to_bytes().unwrap();
+        badge.send_payload(&data_bytes, |_, _| {}).await.unwrap();
+
+        let state = peripheral.state();
+        assert!(state.connected);
+        // Once to connect and once more after the drop
+        assert_eq!(state.discoveries, 2);
+        // Chunks 0 and 1 aren't sent again, so nothing is doubled up
+        assert_eq!(state.writes[2], data_bytes[2 * 16..3 * 16]);
+        assert_eq!(state.writes.concat(), data_bytes);
+    }
+
+    #[tokio::test]
+    async fn upload_stops_reconnecting_after_reconnect_attempts() {
+        let peripheral = MockPeripheral::default();
+        {
+            let mut state = peripheral.state();
+            state.drops = usize::MAX;
+            state.drop_after = 2;
+        }
+        let badge = Badge::from_peripheral(peripheral.clone(), fast_options())
+            .await
+            .unwrap();
+        let data_bytes = flash_data("HELLO").unwrap().to_bytes().unwrap();
+        let result = badge.send_payload(&data_bytes, |_, _| {}).await;
+
+        assert!(matches!(
+            result,
+            Err(BadgerError::WriteFailed { chunk: 2, .. })
+        ));
+        let state = peripheral.state();
+        assert_eq!(state.discoveries, 1 + RECONNECT_ATTEMPTS as usize);
+        assert_eq!(state.writes.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn write_chunk_retries_with_response_after_a_failure() {
+        let peripheral = MockPeripheral::default();
+        peripheral.state().failing_writes = 2;
+        let options = SendOptions {
+            retry_with_response: true,
+            ..fast_options()
+        };
+        let badge = Badge::from_peripheral(peripheral.clone(), options)
+            .await
+            .unwrap();
+        let data_bytes = flash_data("HI").unwrap().to_bytes().unwrap();
+        badge.send_payload(&data_bytes, |_, _| {}).await.unwrap();
+
+        let state = peripheral.state();
+        assert_eq!(state.writes.concat(), data_bytes);
+        assert_eq!(
+            state.write_types[..4],
+            [
+                WriteType::WithoutResponse,
+                WriteType::WithResponse,
+                WriteType::WithResponse,
+                WriteType::WithoutResponse,
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn write_chunk_gives_up_after_write_attempts_on_a_live_link() {
+        let peripheral = MockPeripheral::default();
+        peripheral.state().failing_writes = 3;
+        let badge = Badge::from_peripheral(peripheral.clone(), fast_options())
+            .await
+            .unwrap();
+        let data_bytes = flash_data("HI").unwrap().to_bytes().unwrap();
+        let result = badge.send_payload(&data_bytes, |_, _| {}).await;
+
+        assert!(matches!(
+            result,
+            Err(BadgerError::WriteFailed { chunk: 0, .. })
+        ));
+        let state = peripheral.state();
+        assert_eq!(state.write_types.len(), 3);
+        // The link stayed up, so there was nothing to reconnect
+        assert_eq!(state.discoveries, 1);
+    }
+
+    #[test]
+    fn button_event_reads_the_button_number() {
+        assert_eq!(
+            ButtonEvent::from_notification(&[0x02, 0x00]),
+            Some(ButtonEvent { button: 2 })
+        );
+        assert_eq!(ButtonEvent::from_notification(&[]), None);
+    }
+
+    #[tokio::test]
+    async fn button_events_parses_notifications() {
+        let peripheral = MockPeripheral::default();
+        peripheral.state().button_notifications = vec![vec![1], vec![], vec![2, 0xff]];
+        let badge = Badge::from_peripheral(peripheral.clone(), fast_options())
+            .await
+            .unwrap();
+
+        let events: Vec<ButtonEvent> = badge.button_events().await.unwrap().collect().await;
+        assert_eq!(
+            events,
+            [ButtonEvent { button: 1 }, ButtonEvent { button: 2 }]
+        );
+        assert_eq!(peripheral.state().subscriptions, 1);
+    }
+
+    #[tokio::test]
+    async fn button_events_ends_without_a_notify_characteristic() {
+        let peripheral = MockPeripheral::default();
+        let badge = Badge::from_peripheral(peripheral.clone(), fast_options())
+            .await
+            .unwrap();
+
+        let mut events = badge.button_events().await.unwrap();
+        assert_eq!(events.next().await, None);
+        assert_eq!(peripheral.state().subscriptions, 0);
+    }
+}
@@ -0,0 +1,68 @@
+use thiserror::Error;
+#[cfg(feature = "ble")]
+use uuid::Uuid;
+
+/// Everything that can go wrong building a payload or getting it onto a badge.
+#[derive(Debug, Error)]
+pub enum BadgerError {
+    #[cfg(feature = "ble")]
+    #[error("no Bluetooth adapter found; is Bluetooth enabled?")]
+    AdapterNotFound,
+    #[cfg(feature = "ble")]
+    #[error(
+        "no Bluetooth adapter matches {requested:?}; available adapters: {}",
+        if available.is_empty() { "none".to_string() } else { available.join(", ") }
+    )]
+    AdapterNotAvailable {
+        requested: String,
+        available: Vec<String>,
+    },
+    #[cfg(feature = "ble")]
+    #[error("device not found: no matching badge showed up while scanning")]
+    DeviceNotFound,
+    #[cfg(feature = "ble")]
+    #[error("characteristic {0} not found on the badge")]
+    CharacteristicNotFound(Uuid),
+    #[error("bank {bank} is {width} pixels wide, but the panel only fits {panel_width}")]
+    MessageOverflow {
+        bank: usize,
+        width: usize,
+        panel_width: usize,
+    },
+    #[error("payload has {0} banks, but the badge only holds {max}", max = crate::MAX_BANKS)]
+    TooManyBanks(usize),
+    #[error("bank {bank} is {chunks} chunks wide, more than the size table can encode")]
+    BankTooWide { bank: usize, chunks: usize },
+    #[error("bank {bank} has speed {speed}, but the badge only goes up to {max}", max = crate::MAX_SPEED)]
+    InvalidSpeed { bank: usize, speed: u8 },
+    #[error("no built-in icon called {0:?}")]
+    UnknownIcon(String),
+    #[error("row {0} is outside the panel's 11 rows")]
+    RowOutOfRange(usize),
+    #[error("invalid BDF font at line {line}: {message}")]
+    InvalidFont { line: usize, message: String },
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[cfg(feature = "ble")]
+    #[error("failed to write chunk {chunk}: {source}")]
+    WriteFailed {
+        chunk: usize,
+        #[source]
+        source: btleplug::Error,
+    },
+    #[cfg(feature = "ble")]
+    #[error("{failed} of {total} badges failed to update")]
+    UploadsFailed { failed: usize, total: usize },
+    #[cfg(feature = "ble")]
+    #[error(transparent)]
+    Ble(#[from] btleplug::Error),
+    #[cfg(feature = "image")]
+    #[error("image is empty")]
+    EmptyImage,
+    #[cfg(feature = "image")]
+    #[error(transparent)]
+    Image(#[from] image::ImageError),
+    #[cfg(feature = "serde")]
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+}
@@ -8,6 +8,12 @@ pub const FONT_5X7: &[([u8; 7], char)] = &[
         ],
         'A',
     ),
+    (
+        [
+            0b11110000, 0b10001000, 0b10001000, 0b11110000, 0b10001000, 0b10001000, 0b11110000,
+        ],
+        'B',
+    ),
     (
         [
             0b01110000, 0b10001000, 0b10000000, 0b10000000, 0b10000000, 0b10001000, 0b01110000,
@@ -152,6 +158,162 @@ pub const FONT_5X7: &[([u8; 7], char)] = &[
         ],
         'Z',
     ),
+    (
+        [
+            0b00000000, 0b00000000, 0b01110000, 0b00001000, 0b01111000, 0b10001000, 0b01111000,
+        ],
+        'a',
+    ),
+    (
+        [
+            0b10000000, 0b10000000, 0b10110000, 0b11001000, 0b10001000, 0b10001000, 0b11110000,
+        ],
+        'b',
+    ),
+    (
+        [
+            0b00000000, 0b00000000, 0b01110000, 0b10000000, 0b10000000, 0b10001000, 0b01110000,
+        ],
+        'c',
+    ),
+    (
+        [
+            0b00001000, 0b00001000, 0b01101000, 0b10011000, 0b10001000, 0b10001000, 0b01111000,
+        ],
+        'd',
+    ),
+    (
+        [
+            0b00000000, 0b00000000, 0b01110000, 0b10001000, 0b11111000, 0b10000000, 0b01110000,
+        ],
+        'e',
+    ),
+    (
+        [
+            0b00110000, 0b01001000, 0b01000000, 0b11100000, 0b01000000, 0b01000000, 0b01000000,
+        ],
+        'f',
+    ),
+    (
+        [
+            0b00000000, 0b01111000, 0b10001000, 0b10001000, 0b01111000, 0b00001000, 0b01110000,
+        ],
+        'g',
+    ),
+    (
+        [
+            0b10000000, 0b10000000, 0b10110000, 0b11001000, 0b10001000, 0b10001000, 0b10001000,
+        ],
+        'h',
+    ),
+    (
+        [
+            0b00100000, 0b00000000, 0b01100000, 0b00100000, 0b00100000, 0b00100000, 0b01110000,
+        ],
+        'i',
+    ),
+    (
+        [
+            0b00010000, 0b00000000, 0b00110000, 0b00010000, 0b00010000, 0b10010000, 0b01100000,
+        ],
+        'j',
+    ),
+    (
+        [
+            0b10000000, 0b10000000, 0b10010000, 0b10100000, 0b11000000, 0b10100000, 0b10010000,
+        ],
+        'k',
+    ),
+    (
+        [
+            0b01100000, 0b00100000, 0b00100000, 0b00100000, 0b00100000, 0b00100000, 0b01110000,
+        ],
+        'l',
+    ),
+    (
+        [
+            0b00000000, 0b00000000, 0b11010000, 0b10101000, 0b10101000, 0b10001000, 0b10001000,
+        ],
+        'm',
+    ),
+    (
+        [
+            0b00000000, 0b00000000, 0b10110000, 0b11001000, 0b10001000, 0b10001000, 0b10001000,
+        ],
+        'n',
+    ),
+    (
+        [
+            0b00000000, 0b00000000, 0b01110000, 0b10001000, 0b10001000, 0b10001000, 0b01110000,
+        ],
+        'o',
+    ),
+    (
+        [
+            0b00000000, 0b00000000, 0b11110000, 0b10001000, 0b11110000, 0b10000000, 0b10000000,
+        ],
+        'p',
+    ),
+    (
+        [
+            0b00000000, 0b00000000, 0b01101000, 0b10011000, 0b01111000, 0b00001000, 0b00001000,
+        ],
+        'q',
+    ),
+    (
+        [
+            0b00000000, 0b00000000, 0b10110000, 0b11001000, 0b10000000, 0b10000000, 0b10000000,
+        ],
+        'r',
+    ),
+    (
+        [
+            0b00000000, 0b00000000, 0b01110000, 0b10000000, 0b01110000, 0b00001000, 0b11110000,
+        ],
+        's',
+    ),
+    (
+        [
+            0b01000000, 0b01000000, 0b11100000, 0b01000000, 0b01000000, 0b01001000, 0b00110000,
+        ],
+        't',
+    ),
+    (
+        [
+            0b00000000, 0b00000000, 0b10001000, 0b10001000, 0b10001000, 0b10011000, 0b01101000,
+        ],
+        'u',
+    ),
+    (
+        [
+            0b00000000, 0b00000000, 0b10001000, 0b10001000, 0b10001000, 0b01010000, 0b00100000,
+        ],
+        'v',
+    ),
+    (
+        [
+            0b00000000, 0b00000000, 0b10001000, 0b10001000, 0b10101000, 0b10101000, 0b01010000,
+        ],
+        'w',
+    ),
+    (
+        [
+            0b00000000, 0b00000000, 0b10001000, 0b01010000, 0b00100000, 0b01010000, 0b10001000,
+        ],
+        'x',
+    ),
+    (
+        [
+            0b00000000, 0b00000000, 0b10001000, 0b10001000, 0b01111000, 0b00001000, 0b01110000,
+        ],
+        'y',
+    ),
+    (
+        [
+            0b00000000, 0b00000000, 0b11111000, 0b00010000, 0b00100000, 0b01000000, 0b11111000,
+        ],
+        'z',
+    ),
     (
         [
             0b11111000, 0b10000000, 0b10000000, 0b10000000, 0b10000000, 0b10000000, 0b11111000,
@@ -262,10 +424,183 @@ pub const FONT_5X7: &[([u8; 7], char)] = &[
     ),
 ];
 
-pub fn get_char_data(c: char) -> [u8; 7] {
+// Large 6x11 digit font for counters and scoreboards
+// Each glyph uses the full 11 rows of a chunk, with the 6 most significant
+// bits of each row used for pixels
+pub const FONT_6X11: &[([u8; 11], char)] = &[
+    (
+        [
+            0b01111000, 0b11001100, 0b11001100, 0b11001100, 0b11001100, 0b11001100, 0b11001100,
+            0b11001100, 0b11001100, 0b11001100, 0b01111000,
+        ],
+        '0',
+    ),
+    (
+        [
+            0b00110000, 0b01110000, 0b11110000, 0b00110000, 0b00110000, 0b00110000, 0b00110000,
+            0b00110000, 0b00110000, 0b00110000, 0b11111100,
+        ],
+        '1',
+    ),
+    (
+        [
+            0b01111000, 0b11001100, 0b00001100, 0b00001100, 0b00011000, 0b00110000, 0b01100000,
+            0b11000000, 0b11000000, 0b11000000, 0b11111100,
+        ],
+        '2',
+    ),
+    (
+        [
+            0b01111000, 0b11001100, 0b00001100, 0b00001100, 0b00111000, 0b00001100, 0b00001100,
+            0b00001100, 0b00001100, 0b11001100, 0b01111000,
+        ],
+        '3',
+    ),
+    (
+        [
+            0b00011000, 0b00111000, 0b01111000, 0b11011000, 0b11011000, 0b11111100, 0b00011000,
+            0b00011000, 0b00011000, 0b00011000, 0b00011000,
+        ],
+        '4',
+    ),
+    (
+        [
+            0b11111100, 0b11000000, 0b11000000, 0b11000000, 0b11111000, 0b00001100, 0b00001100,
+            0b00001100, 0b00001100, 0b11001100, 0b01111000,
+        ],
+        '5',
+    ),
+    (
+        [
+            0b01111000, 0b11001100, 0b11000000, 0b11000000, 0b11111000, 0b11001100, 0b11001100,
+            0b11001100, 0b11001100, 0b11001100, 0b01111000,
+        ],
+        '6',
+    ),
+    (
+        [
+            0b11111100, 0b00001100, 0b00001100, 0b00011000, 0b00011000, 0b00110000, 0b00110000,
+            0b01100000, 0b01100000, 0b01100000, 0b01100000,
+        ],
+        '7',
+    ),
+    (
+        [
+            0b01111000, 0b11001100, 0b11001100, 0b11001100, 0b01111000, 0b11001100, 0b11001100,
+            0b11001100, 0b11001100, 0b11001100, 0b01111000,
+        ],
+        '8',
+    ),
+    (
+        [
+            0b01111000, 0b11001100, 0b11001100, 0b11001100, 0b11001100, 0b01111100, 0b00001100,
+            0b00001100, 0b00001100, 0b11001100, 0b01111000,
+        ],
+        '9',
+    ),
+    (
+        [
+            0b00000000, 0b00000000, 0b00000000, 0b00000000, 0b00000000, 0b11111100, 0b00000000,
+            0b00000000, 0b00000000, 0b00000000, 0b00000000,
+        ],
+        '-',
+    ),
+    (
+        [
+            0b00000000, 0b00000000, 0b00110000, 0b00110000, 0b00000000, 0b00000000, 0b00000000,
+            0b00110000, 0b00110000, 0b00000000, 0b00000000,
+        ],
+        ':',
+    ),
+    (
+        [
+            0b00000000, 0b00000000, 0b00000000, 0b00000000, 0b00000000, 0b00000000, 0b00000000,
+            0b00000000, 0b00000000, 0b00000000, 0b00000000,
+        ],
+        ' ',
+    ),
+];
+
+/// The glyph sets `Bitmap` can render text with.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FontSet {
+    /// The 5x7 font, centered vertically on the panel.
+    #[default]
+    Small,
+    /// The full-height 6x11 font. Only covers digits, `-`, `:` and space.
+    Large,
+}
+
+/// Drawn in place of characters a font has no glyph for: a filled 5x7 box,
+/// so they stand out instead of vanishing.
+pub const FALLBACK_GLYPH: [u8; 7] = [0b11111000; 7];
+
+/// Anything `Bitmap` can look up 11-row glyph chunks in.
+pub trait Font {
+    /// Returns the 11-row chunk for `c` in this font, or `FALLBACK_GLYPH` if
+    /// it has none.
+    fn glyph(&self, c: char) -> [u8; 11];
+
+    /// Whether the font has a glyph of its own for `c`.
+    fn has_glyph(&self, c: char) -> bool;
+}
+
+impl<F: Font + ?Sized> Font for &F {
+    fn glyph(&self, c: char) -> [u8; 11] {
+        (**self).glyph(c)
+    }
+
+    fn has_glyph(&self, c: char) -> bool {
+        (**self).has_glyph(c)
+    }
+}
+
+impl Font for FontSet {
+    fn glyph(&self, c: char) -> [u8; 11] {
+        let glyph = match self {
+            FontSet::Small => get_char_data(c).map(|rows| center_rows(&rows)),
+            FontSet::Large => get_large_char_data(c),
+        };
+        glyph.unwrap_or_else(|| center_rows(&FALLBACK_GLYPH))
+    }
+
+    fn has_glyph(&self, c: char) -> bool {
+        match self {
+            FontSet::Small => get_char_data(c).is_some(),
+            FontSet::Large => get_large_char_data(c).is_some(),
+        }
+    }
+}
+
+/// Returns the characters in `s` that `font` has no glyph for, each once, in
+/// the order they first appear.
+pub fn missing_glyphs(font: impl Font, s: &str) -> Vec<char> {
+    let mut missing = vec![];
+    for c in s.chars() {
+        if !font.has_glyph(c) && !missing.contains(&c) {
+            missing.push(c);
+        }
+    }
+    missing
+}
+
+/// Pads a 7-row glyph out to an 11-row chunk, leaving 2 blank rows above.
+pub(crate) fn center_rows(glyph: &[u8; 7]) -> [u8; 11] {
+    let mut rows = [0u8; 11];
+    rows[2..9].copy_from_slice(glyph);
+    rows
+}
+
+pub fn get_large_char_data(c: char) -> Option<[u8; 11]> {
+    FONT_6X11
+        .iter()
+        .find(|(_, ch)| *ch == c)
+        .map(|(data, _)| *data)
+}
+
+pub fn get_char_data(c: char) -> Option<[u8; 7]> {
     FONT_5X7
         .iter()
-        .find(|(_, ch)| *ch == c.to_ascii_uppercase())
+        .find(|(_, ch)| *ch == c)
         .map(|(data, _)| *data)
-        .unwrap_or_else(|| FONT_5X7.last().unwrap().0) // Return space for unknown chars
 }
@@ -0,0 +1,76 @@
+//! Built-in icons, each a single 11-row chunk drawn like the glyphs in
+//! `font`, with the most significant bit of each row as the leftmost pixel.
+
+pub const ICONS: &[([u8; 11], &str)] = &[
+    (
+        [
+            0b00000000, 0b00000000, 0b01101100, 0b11111110, 0b11111110, 0b11111110, 0b01111100,
+            0b00111000, 0b00010000, 0b00000000, 0b00000000,
+        ],
+        "heart",
+    ),
+    (
+        [
+            0b00000000, 0b00000000, 0b00010000, 0b00110000, 0b01111110, 0b11111110, 0b01111110,
+            0b00110000, 0b00010000, 0b00000000, 0b00000000,
+        ],
+        "arrow-left",
+    ),
+    (
+        [
+            0b00000000, 0b00000000, 0b00010000, 0b00011000, 0b11111100, 0b11111110, 0b11111100,
+            0b00011000, 0b00010000, 0b00000000, 0b00000000,
+        ],
+        "arrow-right",
+    ),
+    (
+        [
+            0b00000000, 0b00010000, 0b00111000, 0b01111100, 0b11111110, 0b00111000, 0b00111000,
+            0b00111000, 0b00111000, 0b00111000, 0b00000000,
+        ],
+        "arrow-up",
+    ),
+    (
+        [
+            0b00000000, 0b00111000, 0b00111000, 0b00111000, 0b00111000, 0b00111000, 0b11111110,
+            0b01111100, 0b00111000, 0b00010000, 0b00000000,
+        ],
+        "arrow-down",
+    ),
+    (
+        [
+            0b00000000, 0b00111000, 0b01000100, 0b10101010, 0b10000010, 0b11000110, 0b10111010,
+            0b01000100, 0b00111000, 0b00000000, 0b00000000,
+        ],
+        "smiley",
+    ),
+    (
+        [
+            0b00000000, 0b00000000, 0b00000000, 0b11111110, 0b10111010, 0b10111011, 0b10111011,
+            0b10111010, 0b11111110, 0b00000000, 0b00000000,
+        ],
+        "battery",
+    ),
+    (
+        [
+            0b00000000, 0b00010000, 0b00010000, 0b00111000, 0b11111110, 0b01111100, 0b00111000,
+            0b01101100, 0b01000100, 0b00000000, 0b00000000,
+        ],
+        "star",
+    ),
+    (
+        [
+            0b00000000, 0b00000000, 0b00000010, 0b00000110, 0b00001100, 0b10011000, 0b11110000,
+            0b01100000, 0b00000000, 0b00000000, 0b00000000,
+        ],
+        "check",
+    ),
+];
+
+/// Returns the chunk for the icon called `name`.
+pub fn get_icon(name: &str) -> Option<[u8; 11]> {
+    ICONS
+        .iter()
+        .find(|(_, icon)| *icon == name)
+        .map(|(data, _)| *data)
+}
@@ -0,0 +1,1627 @@
+//! Payload encoding for LSLED Bluetooth LED name badges.
+//!
+//! Building a payload is plain data and byte shuffling, synchronous and
+//! without a runtime: with `default-features = false` the crate is just the
+//! codec. The `ble` feature adds `ble::Badge` for sending payloads to a badge,
+//! pulling in btleplug and tokio, `image` and `serde` enable image loading and
+//! JSON, and `cli` builds the `badger` tool on top of all of them.
+
+#[cfg(feature = "image")]
+use std::path::Path;
+use std::str::FromStr;
+
+#[cfg(feature = "image")]
+use std::fs::File;
+#[cfg(feature = "image")]
+use std::io::BufReader;
+
+#[cfg(feature = "image")]
+use image::codecs::gif::GifDecoder;
+#[cfg(feature = "image")]
+use image::imageops::FilterType;
+#[cfg(feature = "image")]
+use image::{AnimationDecoder, DynamicImage, LumaA};
+
+pub mod bdf;
+#[cfg(feature = "ble")]
+pub mod ble;
+mod error;
+pub mod font;
+#[cfg(feature = "serde")]
+mod hex;
+pub mod icons;
+#[cfg(feature = "serde")]
+pub mod preset;
+#[cfg(feature = "ble")]
+pub use ble::{flash, flash_with, send, send_with};
+pub use error::BadgerError;
+use font::{Font, FontSet};
+
+/// Width of the badge's LED panel in pixels.
+pub const PANEL_WIDTH: usize = 44;
+
+/// How many banks (bitmaps) a single payload can hold.
+pub const MAX_BANKS: usize = 8;
+
+/// The fastest speed a bank can have, since it only gets the high nibble of
+/// its mode byte and the firmware doesn't go past 7.
+pub const MAX_SPEED: u8 = 7;
+
+/// Most frames `Bitmap::put_gif` keeps. At 66 bytes a frame this keeps an
+/// animation to about 2 KiB, a conservative guess at what the badge's flash
+/// holds rather than a documented firmware limit.
+pub const MAX_ANIMATION_FRAMES: usize = 32;
+
+/// Pixel columns covered by each 11-row chunk, one bit per column.
+pub const CHUNK_WIDTH: usize = 8;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(u8)]
+pub enum Mode {
+    #[default]
+    ScrollLeft = 0,
+    ScrollRight = 1,
+    ScrollUp = 2,
+    ScrollDown = 3,
+    Fixed = 4,
+    Animation = 5,
+    Snowflake = 6,
+    Picture = 7,
+    Laser = 8,
+}
+
+impl FromStr for Mode {
+    type Err = String;
+
+    /// Parses a mode name case-insensitively, with or without dashes or
+    /// underscores between words, e.g. `scroll-left` or `ScrollLeft`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name: String = s
+            .chars()
+            .filter(|c| *c != '-' && *c != '_')
+            .map(|c| c.to_ascii_lowercase())
+            .collect();
+
+        match name.as_str() {
+            "scrollleft" => Ok(Mode::ScrollLeft),
+            "scrollright" => Ok(Mode::ScrollRight),
+            "scrollup" => Ok(Mode::ScrollUp),
+            "scrolldown" => Ok(Mode::ScrollDown),
+            "fixed" => Ok(Mode::Fixed),
+            "animation" => Ok(Mode::Animation),
+            "snowflake" => Ok(Mode::Snowflake),
+            "picture" => Ok(Mode::Picture),
+            "laser" => Ok(Mode::Laser),
+            _ => Err(format!("unknown mode {:?}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Bitmap {
+    pub flash: bool,
+    pub marquee: bool,
+    pub mode: Mode,
+    pub speed: u8,
+    /// Light the background and leave the content dark. Applied by `to_bytes`,
+    /// so text and images are inverted alike.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub invert: bool,
+    #[cfg_attr(feature = "serde", serde(with = "hex"))]
+    pub data: Vec<u8>,
+    /// Per-column colors for RGB badges, empty for monochrome content.
+    /// `to_bytes` only knows the monochrome protocol and ignores these.
+    pub colors: Vec<Color>,
+}
+
+impl Bitmap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the width of the bitmap in pixel columns.
+    pub fn width(&self) -> usize {
+        self.data.chunks_exact(11).count() * CHUNK_WIDTH
+    }
+
+    /// Lights the pixel at column `x`, row `y`, growing the bitmap with blank
+    /// chunks if `x` is past its current width.
+    pub fn set_pixel(&mut self, x: usize, y: usize) -> Result<(), BadgerError> {
+        if y >= 11 {
+            return Err(BadgerError::RowOutOfRange(y));
+        }
+
+        let chunk = x / CHUNK_WIDTH;
+        if self.data.len() < (chunk + 1) * 11 {
+            self.data.resize((chunk + 1) * 11, 0);
+        }
+        self.data[chunk * 11 + y] |= 0x80 >> (x % CHUNK_WIDTH);
+        self.fit_colors();
+        Ok(())
+    }
+
+    /// Keeps a colored bitmap's `colors` at one entry per column after its
+    /// width changed, giving new columns the default color. Monochrome
+    /// bitmaps are left without colors.
+    fn fit_colors(&mut self) {
+        if !self.colors.is_empty() {
+            self.colors.resize(self.width(), Color::default());
+        }
+    }
+
+    /// Turns off the pixel at column `x`, row `y`. Pixels past the current
+    /// width are already off, so the bitmap never grows.
+    pub fn clear_pixel(&mut self, x: usize, y: usize) -> Result<(), BadgerError> {
+        if y >= 11 {
+            return Err(BadgerError::RowOutOfRange(y));
+        }
+
+        if let Some(row) = self.data.get_mut(x / CHUNK_WIDTH * 11 + y) {
+            *row &= !(0x80 >> (x % CHUNK_WIDTH));
+        }
+        Ok(())
+    }
+
+    /// Returns the width up to and including the last column with a lit
+    /// pixel, ignoring the blank padding at the end of the last chunk.
+    pub fn content_width(&self) -> usize {
+        (0..self.width())
+            .rev()
+            .find(|&x| (0..11).any(|y| self.pixel(x, y)))
+            .map_or(0, |x| x + 1)
+    }
+
+    /// Returns every pixel column, with bit 0 as the top row.
+    fn pixel_columns(&self) -> Vec<u16> {
+        self.data
+            .chunks_exact(11)
+            .flat_map(|chunk| (0..CHUNK_WIDTH).map(|x| chunk_column(chunk, x)))
+            .collect()
+    }
+
+    /// Returns whether the pixel at column `x`, row `y` is lit.
+    pub fn pixel(&self, x: usize, y: usize) -> bool {
+        y < 11
+            && self
+                .data
+                .get(x / CHUNK_WIDTH * 11 + y)
+                .is_some_and(|row| row & (0x80 >> (x % CHUNK_WIDTH)) != 0)
+    }
+
+    /// Builds a bitmap from 7-pixel columns laid out like the small font's
+    /// content rows, with bit 0 as the top pixel. The width is padded up to a
+    /// whole number of chunks.
+    pub fn from_columns(columns: Vec<u8>) -> Self {
+        let mut bitmap = Self::new();
+        bitmap.data = vec![0; columns.len().div_ceil(CHUNK_WIDTH) * 11];
+
+        for (x, column) in columns.into_iter().enumerate() {
+            for y in 0..7 {
+                if column & (1 << y) != 0 {
+                    bitmap.data[x / CHUNK_WIDTH * 11 + y + 2] |= 0x80 >> (x % CHUNK_WIDTH);
+                }
+            }
+        }
+
+        bitmap
+    }
+
+    /// Returns the 7 content rows of each column, the inverse of `from_columns`.
+    pub fn columns(&self) -> impl Iterator<Item = u8> + '_ {
+        (0..self.width()).map(|x| {
+            (0..7)
+                .filter(|&y| self.pixel(x, y + 2))
+                .fold(0, |column, y| column | 1 << y)
+        })
+    }
+
+    /// Flips the bitmap horizontally, for badges worn facing away. Only the
+    /// columns up to the last lit one are flipped, so the blank padding at the
+    /// end of the last chunk doesn't end up in front of the content.
+    ///
+    /// Modes that step through the bank a screen at a time, `Mode::Animation`
+    /// and the `put_string_paged` layout of `Mode::ScrollUp` and
+    /// `Mode::ScrollDown`, have each screen flipped across the panel in place
+    /// instead, keeping their order.
+    pub fn mirror(&mut self) {
+        let width = self.width();
+        let paged = matches!(
+            self.mode,
+            Mode::Animation | Mode::ScrollUp | Mode::ScrollDown
+        );
+        let order: Vec<usize> = if paged {
+            // Content sits at the start of each screen, see `push_frame`
+            let screen = PANEL_WIDTH.div_ceil(CHUNK_WIDTH) * CHUNK_WIDTH;
+            (0..width)
+                .step_by(screen)
+                .flat_map(|start| {
+                    let visible = start + (width - start).min(PANEL_WIDTH);
+                    (start..visible)
+                        .rev()
+                        .chain(visible..(start + screen).min(width))
+                })
+                .collect()
+        } else {
+            (0..self.content_width()).rev().collect()
+        };
+
+        let columns = self.pixel_columns();
+        self.data = pack_columns(&order.iter().map(|&x| columns[x]).collect::<Vec<_>>());
+        if !self.colors.is_empty() {
+            self.colors = order
+                .iter()
+                .map(|&x| self.colors.get(x).copied().unwrap_or_default())
+                .collect();
+            self.fit_colors();
+        }
+    }
+
+    /// Moves the lit columns to the left edge, the middle or the right edge of
+    /// a panel `panel_width` pixels wide, padding with blank columns. Bitmaps
+    /// that are already at least as wide as the panel are left alone.
+    pub fn align(&mut self, align: Align, panel_width: usize) {
+        let columns = self.pixel_columns();
+        let first = columns.iter().position(|&column| column != 0);
+        let last = columns.iter().rposition(|&column| column != 0);
+        let Some((first, last)) = first.zip(last) else {
+            return;
+        };
+        let content = &columns[first..=last];
+        if content.len() >= panel_width {
+            return;
+        }
+
+        let offset = match align {
+            Align::Left => 0,
+            Align::Center => (panel_width - content.len()) / 2,
+            Align::Right => panel_width - content.len(),
+        };
+        let mut aligned = vec![0; offset];
+        aligned.extend_from_slice(content);
+        self.data = pack_columns(&aligned);
+
+        if !self.colors.is_empty() {
+            let mut colors = vec![Color::default(); offset];
+            colors.extend(self.colors.iter().skip(first).take(content.len()));
+            self.colors = colors;
+        }
+    }
+
+    /// Flips the bitmap vertically, so the top row becomes the bottom one.
+    pub fn flip(&mut self) {
+        for chunk in self.data.chunks_exact_mut(11) {
+            chunk.reverse();
+        }
+    }
+
+    /// Repeats the bitmap's content end to end so it appears `times` times.
+    ///
+    /// The firmware has no repeat count of its own: every bank loops until the
+    /// next one is shown, or forever if it's the only bank. Repeating the data
+    /// makes one pass through the bank longer, which is what a repeat count
+    /// can be approximated with.
+    pub fn repeat(&mut self, times: usize) {
+        if times <= 1 {
+            return;
+        }
+        self.fit_colors();
+        self.colors = self.colors.repeat(times);
+        let chunks = self.data.len() / 11 * 11;
+        self.data = self.data[..chunks].repeat(times);
+    }
+
+    /// Returns the columns that differ between `self` and `other`. Columns past
+    /// the end of the narrower bitmap always count as different.
+    pub fn diff(&self, other: &Bitmap) -> Vec<usize> {
+        let common = self.width().min(other.width());
+        let total = self.width().max(other.width());
+
+        (0..common)
+            .filter(|&x| (0..11).any(|y| self.pixel(x, y) != other.pixel(x, y)))
+            .chain(common..total)
+            .collect()
+    }
+
+    /// Draws the bitmap as text, one line per row, with `█` for lit pixels.
+    /// Honors `invert`, so this matches what the badge shows.
+    pub fn render_ascii(&self) -> String {
+        (0..11)
+            .map(|y| {
+                (0..self.width())
+                    .map(|x| {
+                        if self.pixel(x, y) != self.invert {
+                            '█'
+                        } else {
+                            ' '
+                        }
+                    })
+                    .collect::<String>()
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Draws each step of the bank's animation through a window `panel_width`
+    /// pixels wide, in the style of `render_ascii`, one column or row apart.
+    ///
+    /// Scrolling left or right moves the whole bitmap across the window, from
+    /// fully off one edge to fully off the other. Scrolling up or down moves
+    /// each screen's worth of columns through in turn, as laid out by
+    /// `put_string_paged`. Other modes are drawn as a single still frame of
+    /// the first screen.
+    pub fn frames(&self, panel_width: usize) -> Vec<String> {
+        let width = self.width() as isize;
+        let window = panel_width as isize;
+        let draw = |dx: isize, dy: isize| {
+            (0..11)
+                .map(|y| {
+                    (0..window)
+                        .map(|x| {
+                            let (x, y) = (x + dx, y + dy);
+                            let lit = x >= 0 && y >= 0 && self.pixel(x as usize, y as usize);
+                            if lit != self.invert {
+                                '█'
+                            } else {
+                                ' '
+                            }
+                        })
+                        .collect::<String>()
+                })
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+
+        let screen = panel_width.div_ceil(CHUNK_WIDTH) * CHUNK_WIDTH;
+        let screens = (self.width().div_ceil(screen) as isize).max(1);
+        let screen = screen as isize;
+        match self.mode {
+            Mode::ScrollLeft => (-window..=width).map(|dx| draw(dx, 0)).collect(),
+            Mode::ScrollRight => (-window..=width).rev().map(|dx| draw(dx, 0)).collect(),
+            Mode::ScrollUp => (0..screens)
+                .flat_map(|i| (-11..=11).map(move |dy| (i * screen, dy)))
+                .map(|(dx, dy)| draw(dx, dy))
+                .collect(),
+            Mode::ScrollDown => (0..screens)
+                .flat_map(|i| (-11..=11).rev().map(move |dy| (i * screen, dy)))
+                .map(|(dx, dy)| draw(dx, dy))
+                .collect(),
+            _ => vec![draw(0, 0)],
+        }
+    }
+
+    /// Loads the image at `path` as the bitmap's content, scaled to the
+    /// panel's 11 rows with its aspect ratio kept. Pixels at 50% luminance or
+    /// brighter are lit, as are only opaque ones.
+    #[cfg(feature = "image")]
+    pub fn put_image(&mut self, path: impl AsRef<Path>) -> Result<(), BadgerError> {
+        let image = image::open(path)?;
+        if image.width() == 0 || image.height() == 0 {
+            return Err(BadgerError::EmptyImage);
+        }
+
+        let width = (image.width() as u64 * 11).div_ceil(image.height() as u64) as u32;
+        let image = image
+            .resize_exact(width, 11, FilterType::Triangle)
+            .into_luma_alpha8();
+
+        self.data = vec![0; (width as usize).div_ceil(CHUNK_WIDTH) * 11];
+        self.colors.clear();
+        for (x, y, pixel) in image.enumerate_pixels() {
+            if is_lit(pixel) {
+                self.set_pixel(x as usize, y as usize)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Loads the GIF at `path` as an animation: the bitmap's content is
+    /// replaced with one frame per GIF frame, each scaled to fill the panel
+    /// and lit like `put_image`, and `mode` is set to `Mode::Animation`.
+    /// Frames past `MAX_ANIMATION_FRAMES` are dropped.
+    #[cfg(feature = "image")]
+    pub fn put_gif(&mut self, path: impl AsRef<Path>) -> Result<(), BadgerError> {
+        let decoder = GifDecoder::new(BufReader::new(File::open(path)?))?;
+        let frames = decoder
+            .into_frames()
+            .take(MAX_ANIMATION_FRAMES)
+            .collect::<Result<Vec<_>, _>>()?;
+        if frames.is_empty() {
+            return Err(BadgerError::EmptyImage);
+        }
+
+        self.data.clear();
+        self.colors.clear();
+        self.mode = Mode::Animation;
+        for frame in frames {
+            let image = DynamicImage::ImageRgba8(frame.into_buffer())
+                .resize_exact(PANEL_WIDTH as u32, 11, FilterType::Triangle)
+                .into_luma_alpha8();
+            let mut grid = [[false; 11]; PANEL_WIDTH];
+            for (x, y, pixel) in image.enumerate_pixels() {
+                grid[x as usize][y as usize] = is_lit(pixel);
+            }
+            self.push_frame(&grid);
+        }
+
+        Ok(())
+    }
+
+    /// Appends a frame for `Mode::Animation`, given as `PANEL_WIDTH` columns
+    /// of 11 pixels with index 0 as the top row.
+    ///
+    /// Each frame is padded to 6 chunks (48 columns), which is the stride the
+    /// firmware steps through the bank in. The size table's `u16` chunk count
+    /// caps a bank at 10922 frames, but the badge's flash runs out long before
+    /// that: the whole payload has to fit in a few kilobytes, so keep
+    /// animations to a few dozen frames at most.
+    pub fn push_frame(&mut self, frame: &[[bool; 11]; PANEL_WIDTH]) {
+        self.data.extend(pack_columns(&grid_columns(frame)));
+        self.fit_colors();
+    }
+
+    /// Builds a bitmap from columns of 11 pixels, with index 0 as the top row.
+    /// The width is padded up to a whole number of chunks.
+    pub fn from_grid(grid: &[[bool; 11]]) -> Self {
+        Self {
+            data: pack_columns(&grid_columns(grid)),
+            ..Self::new()
+        }
+    }
+
+    /// Appends the built-in icon called `name`, one of `icons::ICONS`, after
+    /// whatever the bitmap already shows, so it can follow text.
+    pub fn put_icon(&mut self, name: &str) -> Result<(), BadgerError> {
+        let icon =
+            icons::get_icon(name).ok_or_else(|| BadgerError::UnknownIcon(name.to_string()))?;
+        self.data.extend_from_slice(&icon);
+        self.fit_colors();
+        Ok(())
+    }
+
+    /// Renders `n` as a decimal number, with a leading minus sign when negative.
+    pub fn from_number(n: i64, font: FontSet) -> Self {
+        let mut bitmap = Self::new();
+        bitmap.put_string_with(&n.to_string(), font);
+        bitmap
+    }
+
+    pub fn put_string(&mut self, s: &str) {
+        self.put_string_with(s, FontSet::Small);
+    }
+
+    /// Renders each run of text in its own color.
+    pub fn put_colored_string(&mut self, runs: &[(&str, Color)]) {
+        self.data.clear();
+        self.colors.clear();
+
+        for &(s, color) in runs {
+            for c in s.chars() {
+                self.data.extend_from_slice(&FontSet::Small.glyph(c));
+                self.colors.extend([color; CHUNK_WIDTH]);
+            }
+        }
+    }
+
+    pub fn put_string_with(&mut self, s: &str, font: impl Font) {
+        self.put_string_spaced(s, font, 0);
+    }
+
+    /// Like `put_string_with`, but with `spacing` blank chunks between
+    /// characters.
+    pub fn put_string_spaced(&mut self, s: &str, font: impl Font, spacing: usize) {
+        self.data.clear();
+        self.colors.clear();
+
+        for (i, c) in s.chars().enumerate() {
+            if i > 0 {
+                self.data.resize(self.data.len() + spacing * 11, 0);
+            }
+            // Add this character's 11-row chunk to the bitmap data
+            self.data.extend_from_slice(&font.glyph(c));
+        }
+    }
+
+    /// Lays `s` out for `Mode::ScrollUp` and `Mode::ScrollDown`, which move
+    /// through the bank one panel-wide screen at a time instead of sliding it
+    /// sideways. The text is wrapped into lines of as many characters as fit
+    /// on the panel, breaking at spaces where possible, and each line is
+    /// padded out to the same 6-chunk screen stride `push_frame` uses.
+    pub fn put_string_paged(&mut self, s: &str, font: impl Font) {
+        let screen_chunks = PANEL_WIDTH.div_ceil(CHUNK_WIDTH);
+        self.data.clear();
+        self.colors.clear();
+
+        for line in wrap_lines(s, PANEL_WIDTH / CHUNK_WIDTH) {
+            let start = self.data.len();
+            for c in line.chars() {
+                self.data.extend_from_slice(&font.glyph(c));
+            }
+            self.data.resize(start + screen_chunks * 11, 0);
+        }
+    }
+
+    /// Renders `s` in the small font with each glyph trimmed to its lit
+    /// columns and `gap` blank columns (at least one) between glyphs, so narrow
+    /// characters take up less room than wide ones. Blank glyphs like space
+    /// are kept 3 columns wide.
+    pub fn put_string_proportional(&mut self, s: &str, gap: usize) {
+        self.put_string_proportional_with(s, gap, FontSet::Small);
+    }
+
+    /// Like `put_string_proportional`, but with glyphs from `font`.
+    pub fn put_string_proportional_with(&mut self, s: &str, gap: usize, font: impl Font) {
+        let mut columns = vec![];
+
+        for (i, c) in s.chars().enumerate() {
+            if i > 0 {
+                columns.extend(std::iter::repeat_n(0, gap.max(1)));
+            }
+
+            let glyph = font.glyph(c);
+            let glyph_columns: Vec<u16> =
+                (0..CHUNK_WIDTH).map(|x| chunk_column(&glyph, x)).collect();
+            let first = glyph_columns.iter().position(|&column| column != 0);
+            let last = glyph_columns.iter().rposition(|&column| column != 0);
+            match first.zip(last) {
+                Some((first, last)) => columns.extend_from_slice(&glyph_columns[first..=last]),
+                None => columns.extend([0; 3]),
+            }
+        }
+
+        self.data = pack_columns(&columns);
+        self.colors.clear();
+    }
+}
+
+/// Whether an image pixel should light its LED: at 50% luminance or brighter,
+/// and opaque.
+#[cfg(feature = "image")]
+fn is_lit(pixel: &LumaA<u8>) -> bool {
+    let [luma, alpha] = pixel.0;
+    luma >= 128 && alpha >= 128
+}
+
+/// Greedily wraps `s` into lines of at most `width` characters, breaking at
+/// spaces, and splitting words that are longer than a line on their own.
+fn wrap_lines(s: &str, width: usize) -> Vec<String> {
+    let mut lines = vec![];
+    let mut line = String::new();
+
+    for word in s.split(' ').filter(|word| !word.is_empty()) {
+        let line_len = line.chars().count();
+        let word_len = word.chars().count();
+        if line_len > 0 && line_len + 1 + word_len <= width {
+            line.push(' ');
+            line.push_str(word);
+            continue;
+        }
+        if line_len > 0 {
+            lines.push(std::mem::take(&mut line));
+        }
+
+        let chars: Vec<char> = word.chars().collect();
+        let mut pieces = chars.chunks(width.max(1));
+        let last = pieces.next_back().unwrap_or_default();
+        lines.extend(pieces.map(|piece| piece.iter().collect()));
+        line = last.iter().collect();
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+
+    lines
+}
+
+/// Returns column `x` of an 11-row chunk, with bit 0 as the top row.
+fn chunk_column(chunk: &[u8], x: usize) -> u16 {
+    chunk
+        .iter()
+        .enumerate()
+        .filter(|(_, row)| *row & (0x80 >> x) != 0)
+        .fold(0, |column, (y, _)| column | 1 << y)
+}
+
+/// Turns columns of 11 pixels into column bitmasks, with bit 0 as the top row.
+fn grid_columns(grid: &[[bool; 11]]) -> Vec<u16> {
+    grid.iter()
+        .map(|column| {
+            (0..11)
+                .filter(|&y| column[y])
+                .fold(0, |bits, y| bits | 1 << y)
+        })
+        .collect()
+}
+
+/// Packs 11-pixel columns, with bit 0 as the top row, into 11-row chunks. The
+/// width is padded up to a whole number of chunks.
+fn pack_columns(columns: &[u16]) -> Vec<u8> {
+    let mut data = vec![0; columns.len().div_ceil(CHUNK_WIDTH) * 11];
+
+    for (x, column) in columns.iter().enumerate() {
+        for y in 0..11 {
+            if column & (1 << y) != 0 {
+                data[x / CHUNK_WIDTH * 11 + y] |= 0x80 >> (x % CHUNK_WIDTH);
+            }
+        }
+    }
+
+    data
+}
+
+/// Where `Bitmap::align` puts content that is narrower than the panel.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Align {
+    #[default]
+    Left,
+    Center,
+    Right,
+}
+
+impl FromStr for Align {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "left" => Ok(Align::Left),
+            "center" | "centre" => Ok(Align::Center),
+            "right" => Ok(Align::Right),
+            _ => Err(format!("unknown alignment {:?}", s)),
+        }
+    }
+}
+
+/// What to do with a `Mode::Fixed` or `Mode::Picture` bank that is wider than
+/// the panel.
+#[derive(Debug, Clone, Copy, Default)]
+pub enum OverwidePolicy {
+    /// Refuse to build the payload.
+    Error,
+    /// Cut the bank off at the panel edge.
+    Truncate,
+    /// Scroll the bank instead of showing it fixed.
+    #[default]
+    AutoScroll,
+}
+
+/// Which of a chunk's 11 row bytes the firmware draws as the top pixel row.
+///
+/// Unlike `Bitmap::flip`, this only changes how `to_bytes` encodes the
+/// chunks, not what the bitmap holds, so previews still show it upright.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum BitOrder {
+    /// The first byte is the top row, as on stock LSLED firmware.
+    #[default]
+    TopFirst,
+    /// The last byte is the top row, for firmware variants that otherwise
+    /// show everything upside down.
+    BottomFirst,
+}
+
+impl FromStr for BitOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "top-first" => Ok(BitOrder::TopFirst),
+            "bottom-first" => Ok(BitOrder::BottomFirst),
+            _ => Err(format!("unknown bit order {:?}", s)),
+        }
+    }
+}
+
+/// A wall-clock time for the badge's real-time clock, which firmware with a
+/// clock display shows.
+///
+/// It's sent as six bytes in this order: year (two digits, so 2024 is 24),
+/// month (1-12), day (1-31), hour (0-23), minute and second, each as a plain
+/// binary value rather than BCD.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Timestamp {
+    pub year: u8,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+impl Timestamp {
+    /// Returns the six header bytes for this time.
+    pub fn to_bytes(self) -> [u8; 6] {
+        [
+            self.year,
+            self.month,
+            self.day,
+            self.hour,
+            self.minute,
+            self.second,
+        ]
+    }
+}
+
+/// How brightly the badge lights its LEDs.
+///
+/// The firmware has four levels rather than a continuous range. Each variant's
+/// value is header byte 5, the second NUL after `wang`, as the reference
+/// `lednamebadge.py` writes it: one bit per dimmer level, with 0 meaning full
+/// brightness, so a payload that leaves it unset is the same as one from
+/// before brightness was supported.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[repr(u8)]
+pub enum Brightness {
+    #[default]
+    Full = 0x00,
+    ThreeQuarters = 0x10,
+    Half = 0x20,
+    Quarter = 0x40,
+}
+
+impl FromStr for Brightness {
+    type Err = String;
+
+    /// Parses a percentage, with or without a trailing `%`: 100, 75, 50 or 25.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.strip_suffix('%').unwrap_or(s) {
+            "100" => Ok(Brightness::Full),
+            "75" => Ok(Brightness::ThreeQuarters),
+            "50" => Ok(Brightness::Half),
+            "25" => Ok(Brightness::Quarter),
+            _ => Err(format!(
+                "unknown brightness {:?}, expected 100, 75, 50 or 25",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Data {
+    pub bitmaps: Vec<Bitmap>,
+    pub bit_order: BitOrder,
+    /// Sets the badge's clock when present. Without it the timestamp bytes
+    /// stay zeroed and the clock is left alone.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub timestamp: Option<Timestamp>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub brightness: Brightness,
+}
+
+impl Data {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push_bitmap(&mut self, bitmap: Bitmap) {
+        self.bitmaps.push(bitmap);
+    }
+
+    /// Builds a payload that leaves the badge dark: a single fixed bank with
+    /// one unlit chunk. A bank with no chunks at all isn't used, since the
+    /// firmware has nothing to draw for it.
+    pub fn blank() -> Self {
+        let mut data = Self::new();
+        data.push_bitmap(Bitmap {
+            mode: Mode::Fixed,
+            data: vec![0; 11],
+            ..Bitmap::new()
+        });
+        data
+    }
+
+    /// Builds a payload that fills all 8 banks, each labelled with the mode it
+    /// demonstrates, so every mode can be checked on a device in one upload.
+    /// `Mode::Picture` is left out since it looks the same as `Mode::Fixed`
+    /// with text.
+    pub fn self_test() -> Self {
+        let banks = [
+            (Mode::ScrollLeft, "LEFT"),
+            (Mode::ScrollRight, "RIGHT"),
+            (Mode::ScrollUp, "UP"),
+            (Mode::ScrollDown, "DOWN"),
+            (Mode::Fixed, "FIXED"),
+            (Mode::Animation, "ANIM"),
+            (Mode::Snowflake, "SNOW"),
+            (Mode::Laser, "LASER"),
+        ];
+
+        let mut data = Self::new();
+        for (mode, label) in banks {
+            let mut bitmap = Bitmap {
+                mode,
+                speed: 4,
+                ..Bitmap::new()
+            };
+            match mode {
+                // These page through the bank a screen at a time
+                Mode::ScrollUp | Mode::ScrollDown => bitmap.put_string_paged(label, FontSet::Small),
+                _ => bitmap.put_string(label),
+            }
+            data.push_bitmap(bitmap);
+        }
+        data
+    }
+
+    /// Serializes the payload, including the raw bitmap data, to JSON.
+    #[cfg(feature = "serde")]
+    pub fn to_json(&self) -> Result<String, BadgerError> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Loads a payload saved with `to_json`.
+    #[cfg(feature = "serde")]
+    pub fn from_json(json: &str) -> Result<Self, BadgerError> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    /// Returns each bank's width in chunks, as encoded in the header's size table.
+    pub fn bank_widths(&self) -> Vec<usize> {
+        self.bitmaps
+            .iter()
+            .map(|bitmap| bitmap.data.chunks_exact(11).count())
+            .collect()
+    }
+
+    /// Applies `policy` to every `Mode::Fixed` or `Mode::Picture` bank wider
+    /// than `panel_width` pixels. Neither moves the content, and the firmware
+    /// doesn't define what happens to what's left over.
+    pub fn resolve_overwide_banks(
+        &mut self,
+        panel_width: usize,
+        policy: OverwidePolicy,
+    ) -> Result<(), BadgerError> {
+        for (i, bitmap) in self.bitmaps.iter_mut().enumerate() {
+            if !matches!(bitmap.mode, Mode::Fixed | Mode::Picture)
+                || bitmap.content_width() <= panel_width
+            {
+                continue;
+            }
+
+            match policy {
+                OverwidePolicy::Error => {
+                    return Err(BadgerError::MessageOverflow {
+                        bank: i,
+                        width: bitmap.content_width(),
+                        panel_width,
+                    });
+                }
+                OverwidePolicy::Truncate => {
+                    let chunks = panel_width.div_ceil(CHUNK_WIDTH);
+                    bitmap.data.truncate(chunks * 11);
+
+                    // Clear the columns of the last chunk that hang off the panel
+                    if let Some(last) = chunks.checked_sub(1) {
+                        let visible = panel_width - last * CHUNK_WIDTH;
+                        let mask = !0xffu8.checked_shr(visible as u32).unwrap_or(0);
+                        for row in bitmap.data.iter_mut().skip(last * 11) {
+                            *row &= mask;
+                        }
+                    }
+                    bitmap.fit_colors();
+                }
+                OverwidePolicy::AutoScroll => bitmap.mode = Mode::ScrollLeft,
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, BadgerError> {
+        let mut data: Vec<u8> = vec![];
+        data.extend(b"wang\0");
+        // brightness, see `Brightness` for the encoding
+        data.push(self.brightness as u8);
+
+        if self.bitmaps.len() > MAX_BANKS {
+            return Err(BadgerError::TooManyBanks(self.bitmaps.len()));
+        }
+
+        let mut flash = 0u8;
+        let mut marquee = 0u8;
+        let mut modes = [0u8; MAX_BANKS];
+        let mut sizes = [0u16; MAX_BANKS];
+
+        for (i, bitmap) in self.bitmaps.iter().enumerate() {
+            if bitmap.flash {
+                flash |= 1 << i;
+            }
+            if bitmap.marquee {
+                marquee |= 1 << i;
+            }
+            if bitmap.speed > MAX_SPEED {
+                return Err(BadgerError::InvalidSpeed {
+                    bank: i,
+                    speed: bitmap.speed,
+                });
+            }
+            modes[i] = bitmap.speed << 4 | (bitmap.mode as u8);
+            let chunks = bitmap.data.chunks_exact(11).count();
+            sizes[i] =
+                u16::try_from(chunks).map_err(|_| BadgerError::BankTooWide { bank: i, chunks })?;
+        }
+
+        data.push(flash);
+        data.push(marquee);
+        data.extend(modes);
+        data.extend(sizes.iter().flat_map(|size| size.to_be_bytes()));
+        // padding
+        data.extend(&[0; 6]);
+        // timestamp, see `Timestamp` for the layout
+        data.extend(self.timestamp.map_or([0; 6], Timestamp::to_bytes));
+        // padding
+        data.extend(&[0; 4]);
+        // separator
+        data.extend(&[0; 16]);
+
+        let mut data_bytes = 0usize;
+
+        for bitmap in &self.bitmaps {
+            let mask = if bitmap.invert { 0xff } else { 0 };
+            for chunk in bitmap.data.chunks_exact(11) {
+                let rows = chunk.iter().map(|row| row ^ mask);
+                match self.bit_order {
+                    BitOrder::TopFirst => data.extend(rows),
+                    BitOrder::BottomFirst => data.extend(rows.rev()),
+                }
+                data_bytes += 11;
+            }
+        }
+
+        // pad the bitmap data to a whole number of 16-byte writes
+        let padding = (16 - data_bytes % 16) % 16;
+        data.extend(&[0].repeat(padding));
+
+        Ok(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text_bitmap(s: &str) -> Bitmap {
+        let mut bitmap = Bitmap::new();
+        bitmap.put_string(s);
+        bitmap
+    }
+
+    #[test]
+    fn to_bytes_header_layout() {
+        let mut data = Data::new();
+        data.push_bitmap(Bitmap {
+            mode: Mode::Fixed,
+            speed: 5,
+            ..text_bitmap("A")
+        });
+        let bytes = data.to_bytes().unwrap();
+
+        assert_eq!(&bytes[0..6], b"wang\0\0");
+        // flash and marquee masks
+        assert_eq!(bytes[6], 0);
+        assert_eq!(bytes[7], 0);
+        // modes: speed << 4 | mode, unused banks zeroed
+        assert_eq!(bytes[8..16], [0x54, 0, 0, 0, 0, 0, 0, 0]);
+        // sizes: big-endian chunk counts
+        assert_eq!(
+            bytes[16..32],
+            [0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]
+        );
+        // padding, timestamp, padding and separator
+        assert_eq!(bytes[32..64], [0; 32]);
+        assert_eq!(bytes[64..75], FontSet::Small.glyph('A'));
+    }
+
+    #[test]
+    fn to_bytes_packs_speed_and_mode() {
+        let mut data = Data::new();
+        data.push_bitmap(Bitmap {
+            mode: Mode::Laser,
+            speed: 7,
+            ..text_bitmap("A")
+        });
+        data.push_bitmap(Bitmap {
+            mode: Mode::ScrollUp,
+            speed: 0,
+            ..text_bitmap("A")
+        });
+        let bytes = data.to_bytes().unwrap();
+
+        assert_eq!(bytes[8..11], [0x78, 0x02, 0]);
+    }
+
+    #[test]
+    fn to_bytes_sizes_are_big_endian() {
+        let mut data = Data::new();
+        data.push_bitmap(Bitmap {
+            data: vec![0; 300 * 11],
+            ..Bitmap::new()
+        });
+        data.push_bitmap(text_bitmap("AB"));
+        let bytes = data.to_bytes().unwrap();
+
+        assert_eq!(bytes[16..20], [0x01, 0x2c, 0x00, 0x02]);
+    }
+
+    #[test]
+    fn to_bytes_flash_and_marquee_bits_follow_bank_index() {
+        let mut data = Data::new();
+        for i in 0..MAX_BANKS {
+            data.push_bitmap(Bitmap {
+                flash: i % 2 == 0,
+                marquee: i == 1 || i == 7,
+                ..text_bitmap("A")
+            });
+        }
+        let bytes = data.to_bytes().unwrap();
+
+        assert_eq!(bytes[6], 0b0101_0101);
+        assert_eq!(bytes[7], 0b1000_0010);
+    }
+
+    #[test]
+    fn to_bytes_pads_to_whole_chunks() {
+        let mut data = Data::new();
+        data.push_bitmap(text_bitmap("ABCDEFGH"));
+        let bytes = data.to_bytes().unwrap();
+
+        // 64 header bytes, 8 glyphs of 11 rows, then padding to 16 bytes
+        assert_eq!(bytes.len(), 160);
+        assert_eq!(bytes[152..], [0; 8]);
+    }
+
+    #[test]
+    fn to_bytes_inverts_only_bitmap_data() {
+        let mut data = Data::new();
+        data.push_bitmap(text_bitmap("A"));
+        let plain = data.to_bytes().unwrap();
+        data.bitmaps[0].invert = true;
+        let inverted = data.to_bytes().unwrap();
+
+        assert_eq!(inverted[..64], plain[..64]);
+        for (inverted, plain) in inverted[64..75].iter().zip(&plain[64..75]) {
+            assert_eq!(*inverted, !plain);
+        }
+    }
+
+    #[test]
+    fn to_bytes_writes_timestamp() {
+        let mut data = Data::new();
+        data.push_bitmap(text_bitmap("A"));
+        data.timestamp = Some(Timestamp {
+            year: 24,
+            month: 12,
+            day: 31,
+            hour: 23,
+            minute: 59,
+            second: 58,
+        });
+        let bytes = data.to_bytes().unwrap();
+
+        assert_eq!(bytes[32..38], [0; 6]);
+        assert_eq!(bytes[38..44], [24, 12, 31, 23, 59, 58]);
+        assert_eq!(bytes[44..48], [0; 4]);
+    }
+
+    #[test]
+    fn to_bytes_writes_brightness() {
+        let mut data = Data::new();
+        data.push_bitmap(text_bitmap("A"));
+        let full = data.to_bytes().unwrap();
+
+        for (percent, byte) in [("100", 0x00), ("75%", 0x10), ("50", 0x20), ("25%", 0x40)] {
+            data.brightness = percent.parse().unwrap();
+            let bytes = data.to_bytes().unwrap();
+            assert_eq!(bytes[5], byte, "{}", percent);
+            assert_eq!(bytes[..5], full[..5]);
+            assert_eq!(bytes[6..], full[6..]);
+        }
+    }
+
+    #[test]
+    fn to_bytes_without_bitmap_data_is_just_the_header() {
+        let empty = Data::new().to_bytes().unwrap();
+        assert_eq!(empty.len(), 64);
+        assert_eq!(&empty[0..6], b"wang\0\0");
+        assert_eq!(empty[6..], [0; 58]);
+
+        let mut data = Data::new();
+        data.push_bitmap(Bitmap::new());
+        let bytes = data.to_bytes().unwrap();
+        assert_eq!(bytes.len(), 64);
+        assert_eq!(bytes[16..18], [0, 0]);
+    }
+
+    #[test]
+    fn blank_is_one_unlit_chunk() {
+        let bytes = Data::blank().to_bytes().unwrap();
+
+        assert_eq!(bytes[8], Mode::Fixed as u8);
+        assert_eq!(bytes[16..18], [0, 1]);
+        assert!(bytes[64..].iter().all(|&row| row == 0));
+    }
+
+    #[test]
+    fn mirror_and_flip_move_pixels_to_the_opposite_edge() {
+        let mut bitmap = Bitmap::new();
+        bitmap.set_pixel(1, 0).unwrap();
+        bitmap.set_pixel(9, 3).unwrap();
+
+        // Flipped across the 10 columns up to the last lit one
+        bitmap.mirror();
+        assert!(bitmap.pixel(8, 0));
+        assert!(bitmap.pixel(0, 3));
+
+        bitmap.flip();
+        assert!(bitmap.pixel(8, 10));
+        assert!(bitmap.pixel(0, 7));
+        assert_eq!(
+            bitmap.data.iter().map(|row| row.count_ones()).sum::<u32>(),
+            2
+        );
+    }
+
+    #[test]
+    fn mirror_flips_each_animation_frame_in_place() {
+        let mut first = [[false; 11]; PANEL_WIDTH];
+        first[0][0] = true;
+        let mut second = [[false; 11]; PANEL_WIDTH];
+        second[10][5] = true;
+
+        let mut bitmap = Bitmap {
+            mode: Mode::Animation,
+            ..Bitmap::new()
+        };
+        bitmap.push_frame(&first);
+        bitmap.push_frame(&second);
+        let width = bitmap.width();
+
+        bitmap.mirror();
+        assert_eq!(bitmap.width(), width);
+        assert!(bitmap.pixel(PANEL_WIDTH - 1, 0));
+        assert!(bitmap.pixel(48 + PANEL_WIDTH - 1 - 10, 5));
+        assert_eq!(
+            bitmap.data.iter().map(|row| row.count_ones()).sum::<u32>(),
+            2
+        );
+    }
+
+    #[test]
+    fn mirror_flips_each_paged_screen_in_place() {
+        let mut original = Bitmap {
+            mode: Mode::ScrollUp,
+            ..Bitmap::new()
+        };
+        original.put_string_paged("HELLO AB", FontSet::Small);
+
+        let mut bitmap = original.clone();
+        bitmap.mirror();
+        assert_eq!(bitmap.width(), original.width());
+
+        for start in (0..original.width()).step_by(48) {
+            for y in 0..11 {
+                for x in 0..PANEL_WIDTH {
+                    assert_eq!(
+                        bitmap.pixel(start + PANEL_WIDTH - 1 - x, y),
+                        original.pixel(start + x, y)
+                    );
+                }
+                for x in PANEL_WIDTH..48 {
+                    assert!(!bitmap.pixel(start + x, y));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn repeat_multiplies_the_bank_size() {
+        let mut bitmap = text_bitmap("AB");
+        let once = bitmap.data.clone();
+        bitmap.repeat(3);
+        assert_eq!(bitmap.data, once.repeat(3));
+
+        let mut data = Data::new();
+        data.push_bitmap(bitmap);
+        let bytes = data.to_bytes().unwrap();
+        assert_eq!(bytes[16..18], [0, 6]);
+    }
+
+    #[test]
+    fn put_string_spaced_counts_blank_chunks_in_sizes() {
+        let mut bitmap = Bitmap::new();
+        bitmap.put_string_spaced("AA", FontSet::Small, 2);
+        assert_eq!(bitmap.data[11..33], [0; 22]);
+
+        let mut data = Data::new();
+        data.push_bitmap(bitmap);
+        assert_eq!(data.to_bytes().unwrap()[16..18], [0, 4]);
+    }
+
+    #[test]
+    fn from_grid_pads_to_whole_chunks() {
+        let mut grid = vec![[false; 11]; 10];
+        grid[0][0] = true;
+        grid[9][10] = true;
+        let bitmap = Bitmap::from_grid(&grid);
+
+        assert_eq!(bitmap.data.len(), 22);
+        assert_eq!(bitmap.data[0], 0x80);
+        assert_eq!(bitmap.data[21], 0x40);
+        assert!(bitmap.pixel(0, 0));
+        assert!(bitmap.pixel(9, 10));
+    }
+
+    #[test]
+    fn unknown_characters_use_the_fallback_glyph() {
+        assert_eq!(font::missing_glyphs(FontSet::Small, "café ü"), ['é', 'ü']);
+        assert_eq!(
+            FontSet::Small.glyph('é'),
+            font::center_rows(&font::FALLBACK_GLYPH)
+        );
+        assert_ne!(FontSet::Small.glyph('a'), FontSet::Small.glyph('A'));
+    }
+
+    #[cfg(feature = "image")]
+    #[test]
+    fn put_gif_scales_each_frame_to_the_panel() {
+        use image::codecs::gif::GifEncoder;
+        use image::{Delay, Frame, Rgba, RgbaImage};
+
+        let path = std::env::temp_dir().join("badger-put-gif-test.gif");
+        {
+            let mut encoder = GifEncoder::new(File::create(&path).unwrap());
+            let white = RgbaImage::from_pixel(4, 2, Rgba([255, 255, 255, 255]));
+            let black = RgbaImage::from_pixel(4, 2, Rgba([0, 0, 0, 255]));
+            encoder
+                .encode_frames([
+                    Frame::from_parts(white, 0, 0, Delay::from_numer_denom_ms(100, 1)),
+                    Frame::from_parts(black, 0, 0, Delay::from_numer_denom_ms(100, 1)),
+                ])
+                .unwrap();
+        }
+
+        let mut bitmap = Bitmap::new();
+        bitmap.put_gif(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(bitmap.mode, Mode::Animation);
+        assert_eq!(bitmap.data.len(), 2 * 6 * 11);
+        assert!(bitmap.pixel(0, 0) && bitmap.pixel(PANEL_WIDTH - 1, 10));
+        assert!(!bitmap.pixel(PANEL_WIDTH, 0));
+        assert!(bitmap.data[66..].iter().all(|&row| row == 0));
+    }
+
+    #[test]
+    fn put_icon_appends_after_text() {
+        let mut bitmap = text_bitmap("A");
+        bitmap.put_icon("heart").unwrap();
+
+        assert_eq!(bitmap.data[..11], FontSet::Small.glyph('A'));
+        assert_eq!(bitmap.data[11..], icons::get_icon("heart").unwrap());
+        assert!(matches!(
+            bitmap.put_icon("nope"),
+            Err(BadgerError::UnknownIcon(_))
+        ));
+    }
+
+    #[test]
+    fn put_string_paged_gives_each_line_a_screen() {
+        let mut bitmap = Bitmap::new();
+        bitmap.put_string_paged("HI THERE FRIENDS", FontSet::Small);
+
+        // "HI", "THERE" and "FRIEN"/"DS" don't share lines, 6 chunks each
+        assert_eq!(bitmap.data.len(), 4 * 6 * 11);
+        assert_eq!(bitmap.data[..11], FontSet::Small.glyph('H'));
+        assert_eq!(bitmap.data[22..66], [0; 44]);
+        assert_eq!(bitmap.data[66..77], FontSet::Small.glyph('T'));
+        assert_eq!(bitmap.data[198..209], FontSet::Small.glyph('D'));
+    }
+
+    #[test]
+    fn frames_scroll_in_the_mode_direction() {
+        let mut bitmap = text_bitmap("I");
+        bitmap.mode = Mode::ScrollLeft;
+        let left = bitmap.frames(8);
+        // from fully off the right edge to fully off the left one
+        assert_eq!(left.len(), 8 + 8 + 1);
+        assert!(!left[0].contains('█'));
+        assert_eq!(left[8], bitmap.render_ascii());
+        assert!(!left[16].contains('█'));
+
+        bitmap.mode = Mode::ScrollRight;
+        let mut right = bitmap.frames(8);
+        right.reverse();
+        assert_eq!(right, left);
+
+        bitmap.mode = Mode::ScrollUp;
+        let up = bitmap.frames(8);
+        assert_eq!(up.len(), 23);
+        assert_eq!(up[11], bitmap.render_ascii());
+        let rows: Vec<&str> = up[11].lines().collect();
+        let next: Vec<&str> = up[12].lines().collect();
+        assert_eq!(next[..10], rows[1..]);
+    }
+
+    #[test]
+    fn align_pads_with_blank_columns() {
+        let mut bitmap = text_bitmap("I");
+        let width = bitmap.content_width();
+
+        bitmap.align(Align::Center, PANEL_WIDTH);
+        let offset = (PANEL_WIDTH - width) / 2;
+        assert_eq!(bitmap.content_width(), offset + width);
+        assert!((0..offset).all(|x| (0..11).all(|y| !bitmap.pixel(x, y))));
+
+        bitmap.align(Align::Right, PANEL_WIDTH);
+        assert_eq!(bitmap.content_width(), PANEL_WIDTH);
+        let mut data = Data::new();
+        data.push_bitmap(bitmap);
+        assert_eq!(data.to_bytes().unwrap()[16..18], [0, 6]);
+    }
+
+    #[test]
+    fn to_bytes_pads_long_payloads_to_whole_writes() {
+        // 30 chunks is 330 bytes of bitmap data, past what a u8 can count.
+        let mut data = Data::new();
+        data.push_bitmap(Bitmap {
+            mode: Mode::Picture,
+            data: vec![0xff; 30 * 11],
+            ..Bitmap::new()
+        });
+        let bytes = data.to_bytes().unwrap();
+
+        assert_eq!(bytes.len(), 64 + 336);
+        assert!(bytes[64..64 + 330].iter().all(|&row| row == 0xff));
+        assert_eq!(bytes[64 + 330..], [0; 6]);
+    }
+
+    #[test]
+    fn from_number_renders_the_sign_and_digits() {
+        let bitmap = Bitmap::from_number(-42, FontSet::Large);
+        let chunks: Vec<&[u8]> = bitmap.data.chunks_exact(11).collect();
+
+        assert_eq!(
+            chunks,
+            [
+                &FontSet::Large.glyph('-')[..],
+                &FontSet::Large.glyph('4')[..],
+                &FontSet::Large.glyph('2')[..],
+            ]
+        );
+    }
+
+    #[test]
+    fn resolve_overwide_banks_covers_pictures_but_not_scrolling_modes() {
+        let mut data = Data::new();
+        for mode in [Mode::Picture, Mode::ScrollLeft, Mode::Animation] {
+            data.push_bitmap(Bitmap {
+                mode,
+                data: vec![0xff; 8 * 11],
+                ..Bitmap::new()
+            });
+        }
+
+        assert!(matches!(
+            data.clone()
+                .resolve_overwide_banks(PANEL_WIDTH, OverwidePolicy::Error),
+            Err(BadgerError::MessageOverflow { bank: 0, .. })
+        ));
+        data.resolve_overwide_banks(PANEL_WIDTH, OverwidePolicy::AutoScroll)
+            .unwrap();
+        let modes: Vec<Mode> = data.bitmaps.iter().map(|bitmap| bitmap.mode).collect();
+        assert_eq!(modes, [Mode::ScrollLeft, Mode::ScrollLeft, Mode::Animation]);
+    }
+
+    #[test]
+    fn resolve_overwide_banks_applies_each_policy() {
+        let overwide = || {
+            let mut data = Data::new();
+            data.push_bitmap(Bitmap {
+                mode: Mode::Fixed,
+                data: vec![0xff; 8 * 11],
+                ..Bitmap::new()
+            });
+            data
+        };
+
+        let mut data = overwide();
+        assert!(matches!(
+            data.resolve_overwide_banks(PANEL_WIDTH, OverwidePolicy::Error),
+            Err(BadgerError::MessageOverflow {
+                bank: 0,
+                width: 64,
+                panel_width: PANEL_WIDTH,
+            })
+        ));
+
+        let mut data = overwide();
+        data.resolve_overwide_banks(PANEL_WIDTH, OverwidePolicy::Truncate)
+            .unwrap();
+        let bitmap = &data.bitmaps[0];
+        assert_eq!(bitmap.mode, Mode::Fixed);
+        assert_eq!(bitmap.data.len(), 6 * 11);
+        assert!((0..11).all(|y| bitmap.pixel(PANEL_WIDTH - 1, y)));
+        assert!((PANEL_WIDTH..48).all(|x| (0..11).all(|y| !bitmap.pixel(x, y))));
+
+        let mut data = overwide();
+        data.resolve_overwide_banks(PANEL_WIDTH, OverwidePolicy::AutoScroll)
+            .unwrap();
+        assert_eq!(data.bitmaps[0].mode, Mode::ScrollLeft);
+        assert_eq!(data.bitmaps[0].data.len(), 8 * 11);
+    }
+
+    #[test]
+    fn diff_lists_the_changed_columns() {
+        let a = text_bitmap("AB");
+        let mut b = a.clone();
+        for x in [2, 5] {
+            if b.pixel(x, 4) {
+                b.clear_pixel(x, 4).unwrap();
+            } else {
+                b.set_pixel(x, 4).unwrap();
+            }
+        }
+        assert_eq!(a.diff(&b), [2, 5]);
+        assert_eq!(a.diff(&a), [] as [usize; 0]);
+
+        // columns only the wider bitmap has always differ
+        let wider = text_bitmap("ABC");
+        assert_eq!(a.diff(&wider), (16..24).collect::<Vec<_>>());
+        assert_eq!(wider.diff(&a), (16..24).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn bottom_first_bit_order_mirrors_each_chunk_vertically() {
+        let mut data = Data::new();
+        data.push_bitmap(text_bitmap("A"));
+        let top_first = data.to_bytes().unwrap();
+        data.bit_order = BitOrder::BottomFirst;
+        let bottom_first = data.to_bytes().unwrap();
+
+        let mut glyph = FontSet::Small.glyph('A');
+        assert_eq!(top_first[64..75], glyph);
+        glyph.reverse();
+        assert_eq!(bottom_first[64..75], glyph);
+        assert_eq!(bottom_first[..64], top_first[..64]);
+        // the bitmap itself is left upright
+        assert_eq!(data.bitmaps[0], text_bitmap("A"));
+    }
+
+    #[test]
+    fn self_test_fills_every_bank_with_a_distinct_mode() {
+        let data = Data::self_test();
+        assert_eq!(data.bitmaps.len(), 8);
+
+        let modes: std::collections::HashSet<u8> = data
+            .bitmaps
+            .iter()
+            .map(|bitmap| bitmap.mode as u8)
+            .collect();
+        assert_eq!(modes.len(), 8);
+        assert!(data.to_bytes().is_ok());
+    }
+
+    #[test]
+    fn colored_bitmaps_keep_a_color_per_column() {
+        let red = Color { r: 255, g: 0, b: 0 };
+        let mut bitmap = Bitmap::new();
+        bitmap.put_colored_string(&[("A", red)]);
+
+        bitmap.put_icon("heart").unwrap();
+        assert_eq!(bitmap.colors.len(), bitmap.width());
+        assert_eq!(bitmap.colors[0], red);
+        assert_eq!(bitmap.colors[bitmap.width() - 1], Color::default());
+
+        bitmap.set_pixel(100, 0).unwrap();
+        assert_eq!(bitmap.colors.len(), bitmap.width());
+
+        bitmap.push_frame(&[[false; 11]; PANEL_WIDTH]);
+        assert_eq!(bitmap.colors.len(), bitmap.width());
+
+        let mut data = Data::new();
+        data.push_bitmap(Bitmap {
+            mode: Mode::Fixed,
+            ..bitmap
+        });
+        data.resolve_overwide_banks(PANEL_WIDTH, OverwidePolicy::Truncate)
+            .unwrap();
+        assert_eq!(data.bitmaps[0].colors.len(), data.bitmaps[0].width());
+    }
+
+    #[test]
+    fn put_colored_string_colors_each_column_by_its_run() {
+        let red = Color { r: 255, g: 0, b: 0 };
+        let blue = Color { r: 0, g: 0, b: 255 };
+        let mut bitmap = Bitmap::new();
+        bitmap.put_colored_string(&[("AB", red), ("C", blue)]);
+
+        assert_eq!(bitmap.width(), 3 * CHUNK_WIDTH);
+        assert_eq!(bitmap.colors.len(), bitmap.width());
+        assert!(bitmap.colors[..2 * CHUNK_WIDTH].iter().all(|&c| c == red));
+        assert!(bitmap.colors[2 * CHUNK_WIDTH..].iter().all(|&c| c == blue));
+    }
+
+    #[test]
+    fn to_bytes_rejects_speeds_past_7() {
+        let mut data = Data::new();
+        data.push_bitmap(Bitmap::new());
+        data.push_bitmap(Bitmap {
+            speed: 8,
+            ..Bitmap::new()
+        });
+
+        assert!(matches!(
+            data.to_bytes(),
+            Err(BadgerError::InvalidSpeed { bank: 1, speed: 8 })
+        ));
+    }
+
+    #[test]
+    fn from_columns_round_trips_through_columns() {
+        let columns = vec![0x01, 0x40, 0x7f, 0x00, 0x2a, 0x15, 0x63, 0x08, 0x55];
+        let bitmap = Bitmap::from_columns(columns.clone());
+
+        // 9 columns take two chunks, and the padding columns come back blank
+        assert_eq!(bitmap.width(), 2 * CHUNK_WIDTH);
+        let round_tripped: Vec<u8> = bitmap.columns().collect();
+        assert_eq!(round_tripped[..columns.len()], columns);
+        assert!(round_tripped[columns.len()..].iter().all(|&c| c == 0));
+    }
+
+    #[test]
+    fn bank_widths_counts_each_banks_chunks() {
+        let mut data = Data::new();
+        data.push_bitmap(Bitmap::from_number(42, FontSet::Small));
+        data.push_bitmap(Bitmap::new());
+        let mut wide = Bitmap::new();
+        wide.set_pixel(100, 0).unwrap();
+        data.push_bitmap(wide);
+
+        assert_eq!(data.bank_widths(), [2, 0, 13]);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn json_round_trips_a_payload() {
+        let mut bitmap = Bitmap {
+            mode: Mode::Laser,
+            speed: 3,
+            flash: true,
+            ..Bitmap::new()
+        };
+        for (x, y) in [(0, 0), (5, 3), (17, 10), (40, 6)] {
+            bitmap.set_pixel(x, y).unwrap();
+        }
+
+        let mut data = Data::new();
+        data.push_bitmap(bitmap);
+        data.push_bitmap(Bitmap::from_number(7, FontSet::Small));
+        data.brightness = Brightness::Half;
+
+        let json = data.to_json().unwrap();
+        assert_eq!(Data::from_json(&json).unwrap(), data);
+    }
+
+    #[test]
+    fn to_bytes_rejects_too_many_banks() {
+        let mut data = Data::new();
+        for _ in 0..=MAX_BANKS {
+            data.push_bitmap(text_bitmap("A"));
+        }
+
+        assert!(matches!(data.to_bytes(), Err(BadgerError::TooManyBanks(9))));
+    }
+}
@@ -1,252 +1,1048 @@
-use btleplug::api::bleuuid::BleUuid;
-use futures::StreamExt;
-use rand::seq::SliceRandom;
-use rand::Rng;
+use chrono::{Datelike, Local, Timelike};
+use clap::builder::PossibleValuesParser;
+use clap::error::ErrorKind;
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
+use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
 use std::error::Error;
-use std::time::Duration;
-use tokio::time::{self, timeout};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::task::JoinHandle;
+use tokio::time::{self, MissedTickBehavior};
 
-use btleplug::api::{Central, CentralEvent, Manager as _, Peripheral, ScanFilter};
-use btleplug::platform::{Adapter, Manager};
+use btleplug::api::{BDAddr, CharPropFlags, Peripheral, WriteType};
+use btleplug::platform::Peripheral as PlatformPeripheral;
 use uuid::Uuid;
 
-mod font;
-use font::get_char_data;
-
-#[derive(Debug, Clone, Copy, Default)]
-#[repr(u8)]
-enum Mode {
-    #[default]
-    ScrollLeft = 0,
-    ScrollRight = 1,
-    ScrollUp = 2,
-    ScrollDown = 3,
-    Fixed = 4,
-    Animation = 5,
-    Snowflake = 6,
-    Picture = 7,
-    Laser = 8,
+use badger::bdf::BdfFont;
+use badger::ble::{
+    self, Badge, SendOptions, BADGE_NAME, CHARACTERISTIC_UUID, SCAN_TIMEOUT, SERVICE_UUID,
+};
+use badger::font::{missing_glyphs, Font, FontSet};
+use badger::icons::ICONS;
+use badger::preset::{MessagePreset, Preset};
+use badger::{
+    Align, BadgerError, BitOrder, Bitmap, Brightness, Data, Mode, OverwidePolicy, Timestamp,
+    PANEL_WIDTH,
+};
+
+/// Returns the current local time as the badge's clock expects it.
+fn local_timestamp() -> Timestamp {
+    let now = Local::now();
+    Timestamp {
+        year: (now.year() % 100) as u8,
+        month: now.month() as u8,
+        day: now.day() as u8,
+        hour: now.hour() as u8,
+        minute: now.minute() as u8,
+        second: now.second() as u8,
+    }
 }
 
-#[derive(Debug, Clone, Default)]
-struct Bitmap {
-    flash: bool,
-    marquee: bool,
-    mode: Mode,
-    speed: u8,
-    data: Vec<u8>,
+/// Returns a progress bar for uploading `data_bytes`, or a hidden one if the
+/// options ask for a line per chunk instead.
+fn progress_bar(options: &SendOptions, data_bytes: &[u8]) -> ProgressBar {
+    if options.verbose {
+        ProgressBar::hidden()
+    } else {
+        ProgressBar::new(data_bytes.chunks_exact(16).count() as u64).with_style(
+            ProgressStyle::with_template("{prefix}{bar:40} {pos}/{len} chunks {msg}")
+                .expect("progress template is valid"),
+        )
+    }
 }
 
-impl Bitmap {
-    fn new() -> Self {
-        Self::default()
+/// Uploads `data_bytes` to `badge`, reporting the chunks written on `progress`.
+async fn upload(
+    badge: &Badge,
+    data_bytes: &[u8],
+    progress: ProgressBar,
+) -> Result<(), BadgerError> {
+    let result = badge
+        .send_payload(data_bytes, |written, _| {
+            progress.set_position(written as u64)
+        })
+        .await;
+    match &result {
+        Ok(()) => progress.finish_with_message("done"),
+        Err(BadgerError::WriteFailed { chunk, .. }) => {
+            progress.abandon_with_message(format!("failed at chunk {}", chunk))
+        }
+        Err(_) => progress.abandon(),
     }
+    result
+}
 
-    fn put_string(&mut self, s: &str) {
-        self.data.clear();
+/// Uploads `data_bytes` on its own task, so the rest of the runtime stays
+/// responsive while it's in progress.
+async fn spawn_upload(badge: &Badge, data_bytes: Vec<u8>) -> Result<(), BadgerError> {
+    println!(
+        "Writing to characteristic {:?}",
+        badge.characteristic().uuid
+    );
 
-        for c in s.chars() {
-            let char_data = get_char_data(c);
+    let upload = tokio::spawn({
+        let badge = badge.clone();
+        async move {
+            let progress = progress_bar(badge.options(), &data_bytes);
+            upload(&badge, &data_bytes, progress).await
+        }
+    });
+    join(upload).await?;
 
-            // Create an 11-row chunk for this character
-            let mut char_rows = vec![0u8; 11];
+    println!(
+        "Done writing to characteristic {:?}",
+        badge.characteristic()
+    );
+    Ok(())
+}
 
-            // Copy the 7 rows of character data, centered vertically
-            for (row_idx, &char_row) in char_data.iter().enumerate() {
-                // Place character with 2 rows padding at top (11-7)/2 = 2
-                char_rows[row_idx + 2] = char_row;
-            }
+/// Waits for a task the CLI spawned. Those are never cancelled, so this only
+/// fails if the task panicked, and then the panic carries on here.
+async fn join<T>(task: JoinHandle<T>) -> T {
+    match task.await {
+        Ok(value) => value,
+        Err(err) => std::panic::resume_unwind(err.into_panic()),
+    }
+}
+
+/// Connects to the badge and prints the value of every readable
+/// characteristic it has, for probing what a particular unit reports, such
+/// as battery level or firmware version.
+///
+/// This doesn't go through `Badge`, which needs the write characteristic, so
+/// that variants without it can still be probed.
+async fn status(options: &SendOptions) -> Result<(), BadgerError> {
+    let peripheral = ble::find_badge(options).await?;
+    if !peripheral.is_connected().await? {
+        peripheral.connect().await?;
+    }
+    peripheral.discover_services().await?;
 
-            // Add this character's rows to the bitmap data
-            self.data.extend_from_slice(&char_rows);
+    for service in peripheral.services() {
+        println!("Service {}", service.uuid);
+        for characteristic in &service.characteristics {
+            let properties = characteristic.properties;
+            if properties.contains(CharPropFlags::READ) {
+                match peripheral.read(characteristic).await {
+                    Ok(value) => {
+                        let hex: Vec<String> =
+                            value.iter().map(|byte| format!("{:02x}", byte)).collect();
+                        println!("  {}: {}", characteristic.uuid, hex.join(" "));
+                    }
+                    Err(err) => println!("  {}: read failed: {}", characteristic.uuid, err),
+                }
+            } else if properties.contains(CharPropFlags::NOTIFY) {
+                println!("  {}: notify only", characteristic.uuid);
+            }
         }
     }
+
+    if !options.no_disconnect {
+        let _ = peripheral.disconnect().await;
+    }
+
+    Ok(())
 }
 
-struct Data {
-    bitmaps: Vec<Bitmap>,
+/// How long `--all` and `--list` scan for badges before acting on the ones
+/// they found.
+const SCAN_WINDOW: Duration = Duration::from_secs(5);
+
+/// The badges `send_many` uploads to.
+enum Targets {
+    /// The badges at these addresses.
+    Addresses(Vec<BDAddr>),
+    /// Every badge named `BADGE_NAME` that shows up within `SCAN_WINDOW`.
+    AllNamed,
 }
 
-impl Data {
-    fn new() -> Self {
-        Self { bitmaps: vec![] }
+/// Scans for `SCAN_WINDOW` and prints every peripheral advertising the badge
+/// service, without connecting to any of them.
+async fn list_devices(options: &SendOptions) -> Result<(), BadgerError> {
+    let devices = ble::scan(options, SCAN_WINDOW, None, |_, _| true).await?;
+
+    if devices.is_empty() {
+        println!("No badges found");
+    }
+    for peripheral in &devices {
+        let name = ble::peripheral_name(peripheral).await?;
+        // Read the properties after the scan, so the RSSI is the latest one
+        let rssi = peripheral
+            .properties()
+            .await?
+            .and_then(|properties| properties.rssi)
+            .map_or(String::from("unknown"), |rssi| format!("{} dBm", rssi));
+        println!("{}  {}  RSSI {}", peripheral.address(), name, rssi);
     }
 
-    fn push_bitmap(&mut self, bitmap: Bitmap) {
-        self.bitmaps.push(bitmap);
+    Ok(())
+}
+
+/// Scans for the badges in `targets` without connecting to them.
+async fn find_badges(
+    targets: &Targets,
+    options: &SendOptions,
+) -> Result<Vec<PlatformPeripheral>, BadgerError> {
+    match targets {
+        Targets::Addresses(addresses) => {
+            let mut unique = addresses.clone();
+            unique.sort();
+            unique.dedup();
+            ble::scan(
+                options,
+                SCAN_TIMEOUT,
+                Some(unique.len()),
+                |peripheral, _| unique.contains(&peripheral.address()),
+            )
+            .await
+        }
+        Targets::AllNamed => {
+            ble::scan(options, SCAN_WINDOW, None, |_, name| {
+                name == Some(BADGE_NAME)
+            })
+            .await
+        }
     }
+}
 
-    fn to_bytes(&self) -> Result<Vec<u8>, Box<dyn Error>> {
-        let mut data: Vec<u8> = vec![];
-        data.extend(b"wang\0\0");
+/// Connects to `peripheral`, uploads `data_bytes` and disconnects.
+async fn send_to(
+    peripheral: PlatformPeripheral,
+    data_bytes: &[u8],
+    options: SendOptions,
+    progress: ProgressBar,
+) -> Result<(), BadgerError> {
+    let badge = Badge::from_peripheral(peripheral, options).await?;
+    let result = upload(&badge, data_bytes, progress).await;
+    let _ = badge.disconnect().await;
 
-        let mut flash = 0u8;
-        let mut marquee = 0u8;
-        let mut modes = [0u8; 8];
-        let mut sizes = [0u16; 8];
+    result
+}
 
-        for (i, bitmap) in self.bitmaps.iter().enumerate() {
-            if bitmap.flash {
-                flash |= 1 << i;
-            }
-            if bitmap.marquee {
-                marquee |= 1 << i;
+/// Uploads `data` to several badges at once, each on its own task, then
+/// reports how each one went. Fails if any of them did.
+async fn send_many(
+    data: &Data,
+    targets: &Targets,
+    options: &SendOptions,
+) -> Result<(), BadgerError> {
+    let data_bytes: Arc<[u8]> = data.to_bytes()?.into();
+    let badges = find_badges(targets, options).await?;
+
+    let mut results = vec![];
+    if let Targets::Addresses(addresses) = targets {
+        for &address in addresses {
+            if !badges.iter().any(|badge| badge.address() == address) {
+                results.push((address, Err(BadgerError::DeviceNotFound)));
             }
-            modes[i] = bitmap.speed << 4 | (bitmap.mode as u8);
-            sizes[i] = bitmap.data.chunks_exact(11).count() as u16;
         }
+    }
+    if badges.is_empty() && results.is_empty() {
+        return Err(BadgerError::DeviceNotFound);
+    }
 
-        data.push(flash);
-        data.push(marquee);
-        data.extend(modes);
-        data.extend(sizes.iter().flat_map(|size| size.to_be_bytes()));
-        // padding
-        data.extend(&[0; 6]);
-        // timestamp - purpose unclear
-        data.extend(&[0; 6]);
-        // padding
-        data.extend(&[0; 4]);
-        // separator
-        data.extend(&[0; 16]);
-
-        let mut data_bytes = 0u8;
-
-        for bitmap_data in self.bitmaps.iter().map(|bitmap| &bitmap.data) {
-            for chunk in bitmap_data.chunks_exact(11) {
-                data.extend(chunk);
-                let new_data_bytes = data_bytes.checked_add(11);
-                if new_data_bytes.is_none() {
-                    continue;
-                }
-                data_bytes = new_data_bytes.unwrap();
+    // Each upload gets its own line, instead of the bars overwriting each other
+    let bars = MultiProgress::new();
+    let uploads: Vec<_> = badges
+        .into_iter()
+        .map(|peripheral| {
+            let data_bytes = Arc::clone(&data_bytes);
+            let options = options.clone();
+            let address = peripheral.address();
+            let progress =
+                bars.add(progress_bar(&options, &data_bytes).with_prefix(format!("{} ", address)));
+            let upload =
+                tokio::spawn(
+                    async move { send_to(peripheral, &data_bytes, options, progress).await },
+                );
+            (address, upload)
+        })
+        .collect();
+    for (address, upload) in uploads {
+        results.push((address, join(upload).await));
+    }
+
+    for (address, result) in &results {
+        match result {
+            Ok(()) => println!("{}: done", address),
+            Err(err) => println!("{}: failed: {}", address, err),
+        }
+    }
+    let failed = results.iter().filter(|(_, result)| result.is_err()).count();
+    if failed > 0 {
+        return Err(BadgerError::UploadsFailed {
+            failed,
+            total: results.len(),
+        });
+    }
+
+    Ok(())
+}
+
+/// How long the simulation holds each frame at a bank's `speed`. The
+/// firmware's timings aren't documented, so this only roughly follows how much
+/// faster each speed step looks on a badge.
+fn frame_delay(speed: u8) -> Duration {
+    Duration::from_millis(120 - 15 * u64::from(speed.min(7)))
+}
+
+/// How long the simulation shows banks that don't scroll.
+const STILL_FRAME_DURATION: Duration = Duration::from_secs(2);
+
+/// Plays every bank's animation in the terminal, one after the other like the
+/// badge does, until interrupted.
+async fn simulate(data: &Data) {
+    loop {
+        for (i, bitmap) in data.bitmaps.iter().enumerate() {
+            let frames = bitmap.frames(PANEL_WIDTH);
+            let delay = if frames.len() > 1 {
+                frame_delay(bitmap.speed)
+            } else {
+                STILL_FRAME_DURATION
+            };
+            for frame in frames {
+                // Clear the screen and move the cursor home before each frame
+                print!("\x1b[2J\x1b[H");
+                println!("Bank {} ({:?}):", i, bitmap.mode);
+                println!("{}", frame);
+                time::sleep(delay).await;
             }
         }
+    }
+}
+
+/// Formats a number of seconds as `MM:SS`.
+fn format_mm_ss(seconds: u64) -> String {
+    format!("{:02}:{:02}", seconds / 60, seconds % 60)
+}
+
+/// Counts down from `seconds` to zero, re-uploading the remaining time every
+/// second over a single connection, then shows a final message. Uploads are
+/// kept at least `min_interval` apart.
+async fn countdown(
+    seconds: u64,
+    min_interval: Duration,
+    options: &SendOptions,
+) -> Result<(), BadgerError> {
+    let mut badge = Badge::connect(options.clone()).await?;
+
+    // A tick can wake up a little later than the one before it, which would
+    // otherwise make an on-time update look too early
+    let mut limiter = RateLimiter::new(min_interval.saturating_sub(TIMER_SLACK));
+    let start = Instant::now();
+    let mut ticks = time::interval(Duration::from_secs(1));
+    // After an upload that overran a tick, carry on from the next one instead
+    // of firing the missed ticks back to back
+    ticks.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
+    let mut shown = None;
+    loop {
+        ticks.tick().await;
+        // Go by the clock rather than counting ticks, since skipped ticks
+        // are seconds that passed all the same
+        let remaining = seconds.saturating_sub(start.elapsed().as_secs());
+        if remaining == 0 {
+            break;
+        }
+        if shown != Some(remaining) && limiter.admit(Instant::now()) {
+            upload_remaining(&mut badge, remaining).await?;
+            shown = Some(remaining);
+        }
+    }
+
+    // Nothing comes after these two to carry the latest state, so wait for
+    // the limiter instead of dropping them
+    limiter.wait().await;
+    upload_remaining(&mut badge, 0).await?;
+
+    // Leave 00:00 up for a second before the final message
+    ticks.tick().await;
+    limiter.wait().await;
+    let mut bitmap = Bitmap {
+        mode: Mode::Fixed,
+        ..Bitmap::new()
+    };
+    bitmap.put_string("DONE");
+
+    let mut data = Data::new();
+    data.push_bitmap(bitmap);
+
+    spawn_upload(&badge, data.to_bytes()?).await?;
+
+    let _ = badge.disconnect().await;
+
+    Ok(())
+}
+
+/// Shows `remaining` seconds on the countdown's badge.
+async fn upload_remaining(badge: &mut Badge, remaining: u64) -> Result<(), BadgerError> {
+    let mut bitmap = Bitmap {
+        mode: Mode::Fixed,
+        ..Bitmap::new()
+    };
+    bitmap.put_string_with(&format_mm_ss(remaining), FontSet::Large);
+
+    let mut data = Data::new();
+    data.push_bitmap(bitmap);
+    data.resolve_overwide_banks(PANEL_WIDTH, OverwidePolicy::default())?;
+
+    spawn_upload(badge, data.to_bytes()?).await?;
+    // Only the first upload after connecting needs waking the badge up
+    badge.options_mut().warmup = false;
+    Ok(())
+}
+
+/// The shortest interval the looping modes re-upload at, so a misconfigured
+/// interval can't hammer the badge and drain its battery.
+const MIN_REUPLOAD_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How much earlier than its interval the countdown lets an update through,
+/// to allow for timer wake-up jitter.
+const TIMER_SLACK: Duration = Duration::from_millis(50);
 
-        let padding = data_bytes % 16;
-        if padding != 0 {
-            data.extend(&[0].repeat(padding as usize));
+/// Coalesces updates in the looping modes so uploads are at least
+/// `min_interval` apart.
+#[derive(Debug, Clone)]
+struct RateLimiter {
+    min_interval: Duration,
+    last_upload: Option<Instant>,
+}
+
+impl RateLimiter {
+    fn new(min_interval: Duration) -> Self {
+        Self {
+            min_interval,
+            last_upload: None,
+        }
+    }
+
+    /// Returns whether an update arriving at `now` should be uploaded. Updates
+    /// that arrive too soon after the last upload are dropped, so the next
+    /// admitted upload carries the latest state. The last update of a run has
+    /// no next one, so it should go through `wait` instead.
+    fn admit(&mut self, now: Instant) -> bool {
+        let due = self
+            .last_upload
+            .is_none_or(|last| now.saturating_duration_since(last) >= self.min_interval);
+        if due {
+            self.last_upload = Some(now);
         }
+        due
+    }
 
-        Ok(data)
+    /// Returns how long after `now` an update would be admitted.
+    fn delay(&self, now: Instant) -> Duration {
+        self.last_upload.map_or(Duration::ZERO, |last| {
+            (last + self.min_interval).saturating_duration_since(now)
+        })
+    }
+
+    /// Waits until an update would be admitted, and counts it as uploaded.
+    async fn wait(&mut self) {
+        time::sleep(self.delay(Instant::now())).await;
+        self.last_upload = Some(Instant::now());
     }
 }
 
-async fn get_central(manager: &Manager) -> Adapter {
-    let adapters = manager.adapters().await.unwrap();
-    adapters.into_iter().next().unwrap()
+impl Default for RateLimiter {
+    fn default() -> Self {
+        Self::new(MIN_REUPLOAD_INTERVAL)
+    }
 }
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn Error>> {
-    pretty_env_logger::init();
+/// Uploads messages to an LSLED badge over Bluetooth LE.
+#[derive(Debug, Parser)]
+#[command(
+    version,
+    about,
+    subcommand_negates_reqs = true,
+    subcommand_precedence_over_arg = true
+)]
+struct Cli {
+    /// Messages to show, one per bank
+    #[arg(required_unless_present_any = ["self_test", "clear", "image", "config", "status", "list"])]
+    messages: Vec<String>,
+
+    /// Display mode: scroll-left, scroll-right, scroll-up, scroll-down, fixed,
+    /// animation, snowflake, picture or laser. Repeat once per message to set
+    /// each one separately [default: fixed]
+    #[arg(long)]
+    mode: Vec<Mode>,
+
+    /// Speed from 0 to 7. Repeat once per message to set each one separately
+    /// [default: 5]
+    #[arg(long, value_parser = clap::value_parser!(u8).range(0..=7))]
+    speed: Vec<u8>,
+
+    /// Flash messages on and off: all of them, or with `--flash=0,2` only the
+    /// ones at these positions, counting from 0
+    #[arg(
+        long,
+        value_name = "INDEX",
+        num_args = 0..,
+        require_equals = true,
+        value_delimiter = ','
+    )]
+    flash: Option<Vec<usize>>,
+
+    /// Run a marquee border around messages: all of them, or with
+    /// `--marquee=1` only the ones at these positions, counting from 0
+    #[arg(
+        long,
+        value_name = "INDEX",
+        num_args = 0..,
+        require_equals = true,
+        value_delimiter = ','
+    )]
+    marquee: Option<Vec<usize>>,
+
+    /// Show a built-in icon after the message. Repeat once per message to set
+    /// each one separately
+    #[arg(
+        long,
+        value_name = "NAME",
+        value_parser = PossibleValuesParser::new(ICONS.iter().map(|(_, name)| *name))
+    )]
+    icon: Vec<String>,
+
+    /// Where to put fixed messages that are narrower than the panel: left,
+    /// center or right [default: left]
+    #[arg(long)]
+    align: Option<Align>,
+
+    /// Trim the blank columns around each character so narrow ones take up
+    /// less room
+    #[arg(long)]
+    proportional: bool,
+
+    /// Blank 8-pixel chunks to put between characters
+    #[arg(
+        long,
+        value_name = "CHUNKS",
+        default_value_t = 0,
+        conflicts_with = "proportional"
+    )]
+    char_spacing: usize,
+
+    /// Render messages with glyphs from a BDF font file, falling back to the
+    /// built-in font for characters it doesn't have
+    #[arg(long, value_name = "PATH")]
+    font: Option<PathBuf>,
+
+    /// Light the background and leave the text or image dark
+    #[arg(long)]
+    invert: bool,
+
+    /// Show each scrolling message this many times per pass. The badge
+    /// still loops forever; this only lengthens each loop by repeating the
+    /// message
+    #[arg(long, value_parser = clap::value_parser!(u16).range(1..))]
+    repeat: Option<u16>,
+
+    /// Flip everything horizontally, for badges worn facing away
+    #[arg(long)]
+    mirror: bool,
+
+    /// Flip everything upside down
+    #[arg(long)]
+    flip: bool,
+
+    /// Which row of each chunk the badge takes as the top one: top-first, or
+    /// bottom-first for firmware that shows everything upside down
+    /// [default: top-first]
+    #[arg(long)]
+    bit_order: Option<BitOrder>,
 
-    let manager = Manager::new().await?;
+    /// Set the badge's clock to the current local time
+    #[arg(long)]
+    set_clock: bool,
 
-    let central = get_central(&manager).await;
+    /// LED brightness in percent: 100, 75, 50 or 25 [default: 100]
+    #[arg(long)]
+    brightness: Option<Brightness>,
 
-    let central_state = central.adapter_state().await.unwrap();
-    println!("CentralState: {:?}", central_state);
+    /// Fail instead of scrolling when a fixed message or a picture is wider
+    /// than the panel
+    #[arg(long)]
+    strict: bool,
 
-    let mut events = central.events().await?;
+    /// Load the messages, with their modes, speeds, flash and marquee
+    /// settings, from a JSON preset file
+    #[arg(
+        long,
+        value_name = "FILE",
+        conflicts_with_all = ["messages", "mode", "speed", "flash", "marquee", "icon"]
+    )]
+    config: Option<PathBuf>,
 
-    let mut scan_filter = ScanFilter::default();
-    let service = Uuid::from_u128(0x0000fee000001000800000805f9b34fb);
-    scan_filter.services.push(service);
+    /// Save the messages and their settings to a JSON preset file for
+    /// `--config`
+    #[arg(long, value_name = "FILE")]
+    save_config: Option<PathBuf>,
 
-    central.start_scan(scan_filter).await?;
+    /// Show an image in picture mode, in a bank after the messages
+    #[arg(long, value_name = "PATH")]
+    image: Vec<PathBuf>,
 
-    while let Some(event) = events.next().await {
-        if let CentralEvent::DeviceDiscovered(device) = event {
-            println!("DeviceDiscovered: {:?}", device);
-            let peripheral = central.peripheral(&device).await?;
-            let properties = peripheral.properties().await?;
-            let local_name = properties
-                .unwrap()
-                .local_name
-                .unwrap_or(String::from("(peripheral name unknown)"));
-            if local_name != "LSLED" {
-                continue;
+    /// Print what the badge will show and exit without connecting
+    #[arg(long)]
+    preview: bool,
+
+    /// Play the messages' animations in the terminal instead of sending them,
+    /// until interrupted
+    #[arg(long, conflicts_with = "preview")]
+    simulate: bool,
+
+    /// Write the raw payload to a file instead of sending it
+    #[arg(long, value_name = "FILE")]
+    output: Option<PathBuf>,
+
+    /// Print the payload as hex, one 16-byte chunk per line
+    #[arg(long)]
+    hex: bool,
+
+    /// Build the payload without connecting to a badge
+    #[arg(long)]
+    dry_run: bool,
+
+    /// How many times to attempt each chunk write before giving up
+    #[arg(
+        long,
+        global = true,
+        default_value_t = 3,
+        value_parser = clap::value_parser!(u32).range(1..)
+    )]
+    write_attempts: u32,
+
+    /// Milliseconds to wait between chunk writes. Raise this if the badge
+    /// shows garbled or shifted content, which means it dropped chunks
+    #[arg(long, global = true, value_name = "MS", default_value_t = 1)]
+    chunk_delay_ms: u64,
+
+    /// Retry failed chunk writes as acknowledged writes with response
+    #[arg(long, global = true)]
+    retry_with_response: bool,
+
+    /// How to write chunks. `auto` uses writes without response when the
+    /// badge supports them and writes with response otherwise
+    #[arg(long, global = true, value_enum, default_value_t = WriteTypeArg::Auto)]
+    write_type: WriteTypeArg,
+
+    /// Bluetooth adapter to use, by its index or part of its name, when the
+    /// machine has more than one [default: the first]
+    #[arg(long, global = true, value_name = "INDEX|NAME")]
+    adapter: Option<String>,
+
+    /// Connect to the badge with this Bluetooth address instead of the first
+    /// one named LSLED. Repeat to upload to several badges at once
+    #[arg(long, global = true)]
+    address: Vec<BDAddr>,
+
+    /// Upload to every badge named LSLED in range at once
+    #[arg(long, conflicts_with = "address")]
+    all: bool,
+
+    /// The BLE service to scan for, for badges that don't use the stock one
+    #[arg(long, global = true, default_value_t = SERVICE_UUID)]
+    service_uuid: Uuid,
+
+    /// The BLE characteristic to write payloads to
+    #[arg(long, global = true, default_value_t = CHARACTERISTIC_UUID)]
+    char_uuid: Uuid,
+
+    /// Resend the first chunk before the upload to wake the badge up
+    #[arg(long, global = true)]
+    warmup: bool,
+
+    /// Print each chunk as it's written instead of showing a progress bar
+    #[arg(short, long, global = true)]
+    verbose: bool,
+
+    /// Leave the connection for the OS to tear down instead of disconnecting
+    #[arg(long, global = true)]
+    no_disconnect: bool,
+
+    /// Upload a payload that demonstrates every mode instead of messages
+    #[arg(long, conflicts_with_all = ["messages", "image", "config", "save_config"])]
+    self_test: bool,
+
+    /// Blank the badge instead of showing messages
+    #[arg(
+        long,
+        conflicts_with_all = ["messages", "image", "self_test", "config", "save_config"]
+    )]
+    clear: bool,
+
+    /// Print the values of the badge's readable characteristics as hex and
+    /// exit
+    #[arg(long, conflicts_with_all = ["messages", "image", "self_test", "clear", "config"])]
+    status: bool,
+
+    /// Scan for a few seconds, print every badge found with its address and
+    /// signal strength, and exit without connecting
+    #[arg(
+        long,
+        visible_alias = "list-devices",
+        conflicts_with_all = ["messages", "image", "self_test", "clear", "config", "status"]
+    )]
+    list: bool,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Count down from the given number of seconds to zero
+    Countdown {
+        seconds: u64,
+
+        /// Shortest time in milliseconds between two uploads. Updates that
+        /// come in sooner are dropped; values below 1000 are raised to 1000
+        #[arg(long, value_name = "MS", default_value_t = MIN_REUPLOAD_INTERVAL.as_millis() as u64)]
+        min_upload_interval_ms: u64,
+    },
+}
+
+/// Values for `--write-type`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum WriteTypeArg {
+    Auto,
+    WithResponse,
+    WithoutResponse,
+}
+
+/// Spreads the values of a repeatable per-message flag across `count`
+/// messages: no values means `default` for all of them, a single value applies
+/// to every message, and otherwise there has to be exactly one per message.
+fn per_message<T: Copy>(
+    flag: &str,
+    values: &[T],
+    count: usize,
+    default: T,
+) -> Result<Vec<T>, String> {
+    match values {
+        [] => Ok(vec![default; count]),
+        [value] => Ok(vec![*value; count]),
+        values if values.len() == count => Ok(values.to_vec()),
+        values => Err(format!(
+            "{} was given {} times for {} messages; pass it once, or once per message",
+            flag,
+            values.len(),
+            count
+        )),
+    }
+}
+
+/// Turns the message positions given to an on/off flag like `--flash` into
+/// one switch per message. The flag on its own selects every message.
+fn selected_messages(
+    flag: &str,
+    indices: Option<&[usize]>,
+    count: usize,
+) -> Result<Vec<bool>, String> {
+    match indices {
+        None => Ok(vec![false; count]),
+        Some([]) => Ok(vec![true; count]),
+        Some(indices) => {
+            let mut selected = vec![false; count];
+            for &i in indices {
+                *selected.get_mut(i).ok_or_else(|| {
+                    format!(
+                        "{} was given message {}, but there are only {} messages",
+                        flag, i, count
+                    )
+                })? = true;
             }
-            println!("Found {}", local_name);
-            if !peripheral.is_connected().await? {
-                println!("Connecting to peripheral {:?}...", &local_name);
-                peripheral.connect().await?;
+            Ok(selected)
+        }
+    }
+}
+
+/// Prints a usage error for arguments clap couldn't validate on its own and
+/// exits.
+fn usage_error<T>(message: String) -> T {
+    Cli::command()
+        .error(ErrorKind::ValueValidation, message)
+        .exit()
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    // Show the library's progress messages unless RUST_LOG says otherwise
+    pretty_env_logger::formatted_builder()
+        .filter_module("badger", log::LevelFilter::Info)
+        .parse_default_env()
+        .init();
+
+    match run().await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("Error: {}", error);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+async fn run() -> Result<(), Box<dyn Error>> {
+    let cli = Cli::parse();
+    let options = SendOptions {
+        warmup: cli.warmup,
+        verbose: cli.verbose,
+        no_disconnect: cli.no_disconnect,
+        adapter: cli.adapter.clone(),
+        address: cli.address.first().copied(),
+        write_attempts: cli.write_attempts,
+        retry_with_response: cli.retry_with_response,
+        write_type: match cli.write_type {
+            WriteTypeArg::Auto => None,
+            WriteTypeArg::WithResponse => Some(WriteType::WithResponse),
+            WriteTypeArg::WithoutResponse => Some(WriteType::WithoutResponse),
+        },
+        chunk_delay: Duration::from_millis(cli.chunk_delay_ms),
+        service_uuid: cli.service_uuid,
+        characteristic_uuid: cli.char_uuid,
+    };
+
+    if let Some(Command::Countdown {
+        seconds,
+        min_upload_interval_ms,
+    }) = cli.command
+    {
+        if cli.address.len() > 1 {
+            usage_error::<()>("countdown only supports a single --address".to_string());
+        }
+        let min_interval = Duration::from_millis(min_upload_interval_ms).max(MIN_REUPLOAD_INTERVAL);
+        countdown(seconds, min_interval, &options).await?;
+        return Ok(());
+    }
+
+    if cli.status {
+        status(&options).await?;
+        return Ok(());
+    }
+    if cli.list {
+        list_devices(&options).await?;
+        return Ok(());
+    }
+
+    // How many characters of each text bank fit on the panel, for the ones
+    // that don't fit whole
+    let mut fits: Vec<Option<usize>> = vec![];
+    let mut data = if cli.self_test {
+        Data::self_test()
+    } else if cli.clear {
+        Data::blank()
+    } else {
+        let messages = match &cli.config {
+            Some(path) => Preset::load(path)?.messages,
+            None => {
+                let count = cli.messages.len();
+                let modes = per_message("--mode", &cli.mode, count, Mode::Fixed)
+                    .unwrap_or_else(usage_error);
+                let speeds =
+                    per_message("--speed", &cli.speed, count, 5).unwrap_or_else(usage_error);
+                let flashes = selected_messages("--flash", cli.flash.as_deref(), count)
+                    .unwrap_or_else(usage_error);
+                let marquees = selected_messages("--marquee", cli.marquee.as_deref(), count)
+                    .unwrap_or_else(usage_error);
+                let icon_names: Vec<Option<&str>> =
+                    cli.icon.iter().map(|name| Some(name.as_str())).collect();
+                let icons =
+                    per_message("--icon", &icon_names, count, None).unwrap_or_else(usage_error);
+
+                cli.messages
+                    .iter()
+                    .enumerate()
+                    .map(|(i, text)| MessagePreset {
+                        text: text.clone(),
+                        icon: icons[i].map(String::from),
+                        mode: modes[i],
+                        speed: speeds[i],
+                        flash: flashes[i],
+                        marquee: marquees[i],
+                    })
+                    .collect()
+            }
+        };
+        if let Some(path) = &cli.save_config {
+            Preset {
+                messages: messages.clone(),
             }
-            peripheral.discover_services().await?;
+            .save(path)?;
+        }
 
-            for service in peripheral.services() {
-                println!("Checking Service: {:?}", service);
+        let custom_font = cli.font.as_deref().map(BdfFont::load).transpose()?;
+        let font: &dyn Font = match &custom_font {
+            Some(font) => font,
+            None => &FontSet::Small,
+        };
 
-                if service.uuid != Uuid::from_u128(0x0000fee000001000800000805f9b34fb) {
-                    continue;
-                }
+        let render = |bitmap: &mut Bitmap, text: &str| {
+            if matches!(bitmap.mode, Mode::ScrollUp | Mode::ScrollDown) {
+                bitmap.put_string_paged(text, font);
+            } else if cli.proportional {
+                bitmap.put_string_proportional_with(text, 1, font);
+            } else {
+                bitmap.put_string_spaced(text, font, cli.char_spacing);
+            }
+        };
 
-                println!(
-                    "Service UUID {}, primary: {}",
-                    service.uuid, service.primary
+        let mut data = Data::new();
+        for (i, preset) in messages.iter().enumerate() {
+            let message = &preset.text;
+            let mut bitmap = Bitmap {
+                flash: preset.flash,
+                marquee: preset.marquee,
+                mode: preset.mode,
+                speed: preset.speed,
+                ..Bitmap::new()
+            };
+            render(&mut bitmap, message);
+            if let Some(icon) = &preset.icon {
+                bitmap.put_icon(icon)?;
+            }
+
+            let missing = missing_glyphs(font, message);
+            if !missing.is_empty() {
+                eprintln!(
+                    "Warning: message {} has characters the font can't show, drawn as boxes: {}",
+                    i,
+                    missing.iter().collect::<String>()
                 );
-                for characteristic in service.characteristics {
-                    println!("  {:?}", characteristic);
-                    if characteristic.uuid != Uuid::from_u128(0x0000fee100001000800000805f9b34fb) {
-                        println!("Skipping characteristic {:?}", characteristic);
-                        continue;
-                    }
+            }
 
-                    println!("Writing to characteristic {:?}", characteristic.uuid);
-
-                    let mut bitmap = Bitmap {
-                        flash: false,
-                        marquee: false,
-                        mode: Mode::Fixed,
-                        speed: 5,
-                        data: vec![],
-                    };
-
-                    let strings = [
-                        "ARAFEDD",
-                        // "1312",
-                        // "FCK AFD",
-                        // "I USE ARCH BTW",
-                        // "PWND",
-                        // "ALL YOUR BASE ARE BELONG TO US",
-                        // "ZIVILBULLE",
-                    ];
-                    let string = strings.choose(&mut rand::thread_rng()).unwrap();
-                    bitmap.put_string(string);
-
-                    let data = Data {
-                        bitmaps: vec![bitmap],
-                    };
-
-                    let data_bytes = data.to_bytes()?;
-                    println!("{} total chunks", data_bytes.chunks_exact(16).count());
-                    for (i, chunk) in data_bytes.chunks_exact(16).enumerate() {
-                        time::sleep(Duration::from_micros(10)).await;
-                        if peripheral
-                            .write(
-                                &characteristic,
-                                chunk,
-                                btleplug::api::WriteType::WithoutResponse,
-                            )
-                            .await
-                            .is_err()
-                        {
-                            println!("Error writing chunk {} of {}", i, data_bytes.len());
-                            continue;
-                        }
-                        println!("Wrote chunk {} of {}", i, data_bytes.len());
-                    }
+            let fit = (bitmap.content_width() > PANEL_WIDTH).then(|| {
+                (0..message.chars().count())
+                    .rev()
+                    .find(|&len| {
+                        let mut prefix = Bitmap::new();
+                        render(&mut prefix, &message.chars().take(len).collect::<String>());
+                        prefix.content_width() <= PANEL_WIDTH
+                    })
+                    .unwrap_or(0)
+            });
+            fits.push(fit);
+            data.push_bitmap(bitmap);
+        }
+        for path in &cli.image {
+            let mut bitmap = Bitmap {
+                mode: Mode::Picture,
+                ..Bitmap::new()
+            };
+            bitmap
+                .put_image(path)
+                .map_err(|err| format!("failed to load {}: {}", path.display(), err))?;
+            data.push_bitmap(bitmap);
+        }
+        data
+    };
+    let fit_note = |bank: usize| {
+        fits.get(bank)
+            .copied()
+            .flatten()
+            .map_or(String::new(), |fit| {
+                format!("; only the first {} characters fit", fit)
+            })
+    };
+    if cli.strict {
+        data.resolve_overwide_banks(PANEL_WIDTH, OverwidePolicy::Error)
+            .map_err(|err| match err {
+                BadgerError::MessageOverflow { bank, .. } => format!("{}{}", err, fit_note(bank)),
+                err => err.to_string(),
+            })?;
+    } else {
+        // resolve_overwide_banks scrolls these, which is rarely what was asked
+        // for, so say so
+        for (i, bitmap) in data.bitmaps.iter().enumerate() {
+            let width = bitmap.content_width();
+            if matches!(bitmap.mode, Mode::Fixed | Mode::Picture) && width > PANEL_WIDTH {
+                eprintln!(
+                    "Warning: bank {} is {} pixels wide, but the panel is {}{}, so it will scroll instead",
+                    i,
+                    width,
+                    PANEL_WIDTH,
+                    fit_note(i)
+                );
+            }
+        }
+        data.resolve_overwide_banks(PANEL_WIDTH, OverwidePolicy::default())?;
+    }
+    for bitmap in &mut data.bitmaps {
+        bitmap.invert |= cli.invert;
+        // Only scrolling banks move through their data, anything else would
+        // just get wider than the panel
+        let scrolls = matches!(
+            bitmap.mode,
+            Mode::ScrollLeft | Mode::ScrollRight | Mode::ScrollUp | Mode::ScrollDown
+        );
+        if let Some(times) = cli.repeat.filter(|_| scrolls) {
+            bitmap.repeat(times.into());
+        }
+        // Mirror first, so the flipped content still ends up where --align
+        // says instead of on the opposite side
+        if cli.mirror {
+            bitmap.mirror();
+        }
+        if let Some(align) = cli.align.filter(|_| bitmap.mode == Mode::Fixed) {
+            bitmap.align(align, PANEL_WIDTH);
+        }
+        if cli.flip {
+            bitmap.flip();
+        }
+    }
+    if cli.set_clock {
+        data.timestamp = Some(local_timestamp());
+    }
+    if let Some(brightness) = cli.brightness {
+        data.brightness = brightness;
+    }
+    if let Some(bit_order) = cli.bit_order {
+        data.bit_order = bit_order;
+    }
 
-                    println!("Done writing to characteristic {:?}", characteristic);
+    if cli.preview {
+        for (i, bitmap) in data.bitmaps.iter().enumerate() {
+            println!("Bank {} ({:?}):", i, bitmap.mode);
+            println!("{}", bitmap.render_ascii());
+        }
+        return Ok(());
+    }
 
-                    let _ = peripheral.disconnect().await;
-                }
-            }
+    if cli.simulate {
+        simulate(&data).await;
+        return Ok(());
+    }
+
+    // Build the payload up front so bad input fails before any BLE work
+    let data_bytes = data.to_bytes()?;
+    if cli.hex {
+        for (i, chunk) in data_bytes.chunks_exact(16).enumerate() {
+            let hex: Vec<String> = chunk.iter().map(|byte| format!("{:02x}", byte)).collect();
+            println!("{:04x}: {}", i * 16, hex.join(" "));
         }
+    }
+    if let Some(path) = &cli.output {
+        std::fs::write(path, &data_bytes)?;
+        println!("Wrote {} bytes to {}", data_bytes.len(), path.display());
+    }
 
-        std::process::exit(0);
+    if cli.dry_run || cli.output.is_some() {
+        return Ok(());
     }
 
+    if cli.all {
+        send_many(&data, &Targets::AllNamed, &options).await?;
+    } else if cli.address.len() > 1 {
+        send_many(&data, &Targets::Addresses(cli.address), &options).await?;
+    } else {
+        let data_bytes = data.to_bytes()?;
+        let peripheral = ble::find_badge(&options).await?;
+        let progress = progress_bar(&options, &data_bytes);
+        send_to(peripheral, &data_bytes, options, progress).await?;
+    }
     Ok(())
 
     // loop {
@@ -305,3 +1101,54 @@ async fn main() -> Result<(), Box<dyn Error>> {
     //     }
     // }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_mm_ss_pads_minutes_and_seconds() {
+        assert_eq!(format_mm_ss(65), "01:05");
+        assert_eq!(format_mm_ss(0), "00:00");
+        assert_eq!(format_mm_ss(600), "10:00");
+    }
+
+    #[test]
+    fn rate_limiter_coalesces_fast_updates() {
+        let start = Instant::now();
+        let mut limiter = RateLimiter::new(Duration::from_secs(1));
+
+        let admitted: Vec<u64> = (0..25)
+            .map(|i| i * 100)
+            .filter(|&ms| limiter.admit(start + Duration::from_millis(ms)))
+            .collect();
+
+        assert_eq!(admitted, [0, 1000, 2000]);
+    }
+
+    #[test]
+    fn rate_limiter_delays_the_final_update_instead_of_dropping_it() {
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+        let mut limiter = RateLimiter::new(Duration::from_secs(5));
+
+        assert!(limiter.admit(at(0)));
+        assert!(limiter.admit(at(5000)));
+        // 00:01 is dropped, and 00:00 has to wait out the rest of the interval
+        assert!(!limiter.admit(at(6000)));
+        assert_eq!(limiter.delay(at(7000)), Duration::from_secs(3));
+        assert!(limiter.admit(at(10000)));
+        assert_eq!(limiter.delay(at(16000)), Duration::ZERO);
+    }
+
+    #[tokio::test]
+    async fn rate_limiter_wait_counts_as_an_upload() {
+        let mut limiter = RateLimiter::new(Duration::from_millis(20));
+        assert!(limiter.admit(Instant::now()));
+
+        let before = Instant::now();
+        limiter.wait().await;
+        assert!(before.elapsed() >= Duration::from_millis(15));
+        assert!(!limiter.admit(Instant::now()));
+    }
+}
@@ -0,0 +1,40 @@
+//! Message presets: what to show and how, saved as JSON so badge content can
+//! be kept under version control.
+
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{BadgerError, Mode};
+
+/// A message as a preset stores it: its text and how to show it, rather than
+/// the rendered pixels a `Bitmap` holds.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MessagePreset {
+    pub text: String,
+    /// A built-in icon shown after the text.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub icon: Option<String>,
+    pub mode: Mode,
+    pub speed: u8,
+    #[serde(default)]
+    pub flash: bool,
+    #[serde(default)]
+    pub marquee: bool,
+}
+
+/// A full set of messages, one per bank.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Preset {
+    pub messages: Vec<MessagePreset>,
+}
+
+impl Preset {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, BadgerError> {
+        Ok(serde_json::from_str(&std::fs::read_to_string(path)?)?)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), BadgerError> {
+        Ok(std::fs::write(path, serde_json::to_string_pretty(self)?)?)
+    }
+}